@@ -1,3 +1,4 @@
+use intcode::{Goal, IntcodeError};
 use std::error::Error;
 use std::fs;
 use std::process;
@@ -14,17 +15,7 @@ fn run(filename: &str) -> Result<(), Box<dyn Error>> {
     // Read the input file
     let contents = fs::read_to_string(filename)?;
     // Convert comma-separated string to vector of ints
-    let mut input_data: Vec<i64> = contents
-        .trim()
-        .split(",")
-        .map(|x| x.parse::<i64>().unwrap())
-        .collect();
-
-    // Replace position 1 with the value 12 and position 2 with the value 2
-    // (see problem statement)
-
-    input_data[1] = 12;
-    input_data[2] = 2;
+    let input_data: Vec<i64> = intcode::parse_program(&contents)?;
 
     /* Program logic
     - Read ints in sets of four
@@ -32,24 +23,13 @@ fn run(filename: &str) -> Result<(), Box<dyn Error>> {
     - int 1,2,3 are the references for the  two operands and the target for the sum/multiplication
     */
 
-    for i in (0..input_data.len()).step_by(4) {
-        let opcode = input_data[i];
-        if opcode == 99 {
-            break;
-        }
-        let left_op_ref: usize = input_data[i + 1] as usize;
-        let right_op_ref: usize = input_data[i + 2] as usize;
-        let target_ref: usize = input_data[i + 3] as usize;
-        if opcode == 1 {
-            input_data[target_ref] = input_data[left_op_ref] + input_data[right_op_ref];
-        } else if opcode == 2 {
-            input_data[target_ref] = input_data[left_op_ref] * input_data[right_op_ref];
-        }
-    }
+    // Replace position 1 with the value 12 and position 2 with the value 2
+    // (see problem statement)
+    let output = run_tape(input_data.clone(), 12, 2)?;
 
     // Exercise wants to know what's at position 0 at the end
 
-    println!("{}", input_data[0]);
+    println!("{}", output);
 
     /* New terminology:
 
@@ -63,43 +43,27 @@ fn run(filename: &str) -> Result<(), Box<dyn Error>> {
     */
     let target_value: i64 = 19690720;
 
-    let initial_memory_state: Vec<i64> = contents
-        .trim()
-        .split(",")
-        .map(|x| x.parse::<i64>().unwrap())
-        .collect();
-
-    for noun in 0..99 {
-        for verb in 0..99 {
-            let mut trial_memory_state = initial_memory_state.clone();
-            trial_memory_state[1] = noun;
-            trial_memory_state[2] = verb;
-            let output = run_tape(trial_memory_state);
-            if output == target_value {
-                println!("noun: {}, verb: {}", noun, verb);
-                break;
-            }
-        }
+    // Leave the noun and verb unknown and solve for position 0 ending up as the target
+    let mut solver = intcode::SymbolicMachine::new(input_data);
+    let noun = solver.symbolic_memory(1, 0..=99);
+    let verb = solver.symbolic_memory(2, 0..=99);
+    match solver.solve(Goal::MemoryAtHalt { address: 0, value: target_value })? {
+        Some(solution) => println!(
+            "noun: {}, verb: {}",
+            solution.value(noun).unwrap_or_default(),
+            solution.value(verb).unwrap_or_default()
+        ),
+        None => println!("no noun and verb give {}", target_value),
     }
 
     Ok(())
 }
 
-fn run_tape(mut memory: Vec<i64>) -> i64 {
-    for i in (0..memory.len()).step_by(4) {
-        let opcode = memory[i];
-        if opcode == 99 {
-            break;
-        }
-        let left_op_ref: usize = memory[i + 1] as usize;
-        let right_op_ref: usize = memory[i + 2] as usize;
-        let target_ref: usize = memory[i + 3] as usize;
-        if opcode == 1 {
-            memory[target_ref] = memory[left_op_ref] + memory[right_op_ref];
-        } else if opcode == 2 {
-            memory[target_ref] = memory[left_op_ref] * memory[right_op_ref];
-        }
-    }
+fn run_tape(memory: Vec<i64>, noun: i64, verb: i64) -> Result<i64, IntcodeError> {
+    let mut machine = intcode::Machine::new(memory);
+    machine.write(1, noun);
+    machine.write(2, verb);
+    machine.run()?;
     // Return the first element once the tape has run
-    memory[0]
+    Ok(machine.read(0))
 }
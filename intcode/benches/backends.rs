@@ -0,0 +1,101 @@
+// Compares the interpreter and threaded backends on two real programs:
+// the day 9 BOOST program in sensor boost mode, and a full game of the
+// day 13 arcade cabinet played by moving the paddle under the ball.
+//
+//     cargo bench -p intcode --bench backends
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+use intcode::{Backend, IntcodeError, Machine, RunState};
+
+const RUNS: u32 = 10;
+
+fn main() {
+    if let Err(e) = run() {
+        println!("Application error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let boost = read_program("day_9")?;
+    let arcade = read_program("day_13")?;
+
+    println!("{:<16} {:>12} {:>12} {:>8}", "program", "interpreter", "threaded", "speedup");
+    compare("day 9 BOOST", |backend| {
+        let mut machine = Machine::with_backend(boost.clone(), backend);
+        machine.push_input(2);
+        machine.run()
+    })?;
+    compare("day 13 arcade", |backend| play_arcade(&arcade, backend).map(|score| vec![score]))?;
+    Ok(())
+}
+
+// Check both backends give the same result, then time each of them.
+fn compare<F>(name: &str, mut run: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(Backend) -> Result<Vec<i64>, IntcodeError>,
+{
+    let expected = run(Backend::Interpreter)?;
+    let actual = run(Backend::Threaded)?;
+    if expected != actual {
+        return Err(format!("{}: interpreter gave {:?}, threaded gave {:?}", name, expected, actual).into());
+    }
+    let interpreter = time(|| run(Backend::Interpreter))?;
+    let threaded = time(|| run(Backend::Threaded))?;
+    println!(
+        "{:<16} {:>12.2?} {:>12.2?} {:>7.2}x",
+        name,
+        interpreter,
+        threaded,
+        interpreter.as_secs_f64() / threaded.as_secs_f64()
+    );
+    Ok(())
+}
+
+// The fastest of several runs.
+fn time<F: FnMut() -> Result<Vec<i64>, IntcodeError>>(mut f: F) -> Result<Duration, IntcodeError> {
+    let mut best = Duration::from_secs(u64::MAX);
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        f()?;
+        best = best.min(start.elapsed());
+    }
+    Ok(best)
+}
+
+// Play the game for free until it ends and return the final score.
+fn play_arcade(program: &[i64], backend: Backend) -> Result<i64, IntcodeError> {
+    let mut program = program.to_vec();
+    program[0] = 2;
+    let mut machine = Machine::with_backend(program, backend);
+    let mut output = Vec::new();
+    let (mut ball, mut paddle, mut score): (i64, i64, i64) = (0, 0, 0);
+    loop {
+        match machine.resume()? {
+            RunState::NeedsInput => machine.push_input((ball - paddle).signum()),
+            RunState::Output(value) => {
+                output.push(value);
+                if let [x, y, tile] = output[..] {
+                    match (x, y, tile) {
+                        (-1, 0, _) => score = tile,
+                        (_, _, 3) => paddle = x,
+                        (_, _, 4) => ball = x,
+                        _ => {}
+                    }
+                    output.clear();
+                }
+            }
+            RunState::Halted => return Ok(score),
+        }
+    }
+}
+
+fn read_program(day: &str) -> Result<Vec<i64>, Box<dyn Error>> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(day).join("input.txt");
+    Ok(intcode::parse_program(&fs::read_to_string(path)?)?)
+}
@@ -0,0 +1,92 @@
+// Day 7 part 2 (all 120 feedback loops of five amplifiers) run three ways:
+// with the cooperative scheduler, with a thread per amplifier, and with every
+// phase permutation's cooperative network on its own thread at once.
+//
+//     cargo bench -p intcode --bench network
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use intcode::{Network, NetworkError, NetworkState, Permutations, Topology};
+
+const RUNS: u32 = 5;
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+fn main() {
+    if let Err(e) = run() {
+        println!("Application error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../day_7/input.txt");
+    let program = intcode::parse_program(&fs::read_to_string(path)?)?;
+    let phases: Vec<Vec<i64>> = Permutations::new(&[5, 6, 7, 8, 9]).collect();
+
+    let (cooperative, best) = time(|| {
+        let mut best = 0;
+        for phases in &phases {
+            best = best.max(feedback_loop(&program, phases, false)?);
+        }
+        Ok(best)
+    })?;
+    let (threaded, threaded_best) = time(|| {
+        let mut best = 0;
+        for phases in &phases {
+            best = best.max(feedback_loop(&program, phases, true)?);
+        }
+        Ok(best)
+    })?;
+    let (parallel, parallel_best) = time(|| {
+        let handles: Vec<_> = phases
+            .iter()
+            .map(|phases| {
+                let (program, phases) = (program.clone(), phases.clone());
+                thread::spawn(move || feedback_loop(&program, &phases, false))
+            })
+            .collect();
+        let mut best = 0;
+        for handle in handles {
+            best = best.max(handle.join().expect("search thread panicked")?);
+        }
+        Ok(best)
+    })?;
+    if threaded_best != best || parallel_best != best {
+        return Err(format!("results differ: {}, {}, {}", best, threaded_best, parallel_best).into());
+    }
+
+    println!("best signal {} from {} permutations", best, phases.len());
+    println!("{:<36} {:>10.2?}", "cooperative scheduler", cooperative);
+    println!("{:<36} {:>10.2?}", "thread per amplifier", threaded);
+    println!("{:<36} {:>10.2?}", "thread per permutation", parallel);
+    Ok(())
+}
+
+fn feedback_loop(program: &[i64], phases: &[i64], threaded: bool) -> Result<i64, NetworkError> {
+    let mut amplifiers = Network::with_phases(program, phases, Topology::Ring).expect("rings only link their own machines");
+    amplifiers.push_input(0, 0);
+    let state = if threaded {
+        amplifiers.run_threaded(TIMEOUT)?
+    } else {
+        amplifiers.run()?
+    };
+    assert_eq!(state, NetworkState::Halted);
+    Ok(*amplifiers.outputs(phases.len() - 1).last().unwrap())
+}
+
+// The fastest of several runs, and its result.
+fn time<F: FnMut() -> Result<i64, NetworkError>>(mut f: F) -> Result<(Duration, i64), NetworkError> {
+    let mut best = Duration::from_secs(u64::MAX);
+    let mut result = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = f()?;
+        best = best.min(start.elapsed());
+    }
+    Ok((best, result))
+}
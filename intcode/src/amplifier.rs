@@ -0,0 +1,101 @@
+// Chains of amplifiers, as in day 7.
+//
+// Each amplifier runs a copy of the same program. It is given its phase
+// setting as its first input, then the signal from the amplifier before it;
+// the first amplifier gets 0. In a linear chain the last amplifier's output
+// is the result. In a feedback loop the last amplifier also feeds the first,
+// and everything keeps running until they halt; the result is the last
+// signal the last amplifier sent.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::network::{Network, NetworkError, NetworkState, Topology};
+use crate::permutations::Permutations;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AmplifierTopology {
+    Linear,
+    Feedback,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AmplifierError {
+    Machine(NetworkError),
+    /// The amplifiers stopped with some of them still waiting for a signal.
+    Deadlocked,
+    /// The last amplifier never produced a signal.
+    NoOutput,
+}
+
+impl fmt::Display for AmplifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmplifierError::Machine(e) => write!(f, "amplifier {}", e),
+            AmplifierError::Deadlocked => write!(f, "amplifiers deadlocked"),
+            AmplifierError::NoOutput => write!(f, "last amplifier halted without output"),
+        }
+    }
+}
+
+impl Error for AmplifierError {}
+
+impl From<NetworkError> for AmplifierError {
+    fn from(e: NetworkError) -> AmplifierError {
+        AmplifierError::Machine(e)
+    }
+}
+
+// The phase settings that gave the strongest signal.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BestConfiguration {
+    pub phases: Vec<i64>,
+    pub signal: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AmplifierChain {
+    program: Vec<i64>,
+    topology: AmplifierTopology,
+}
+
+impl AmplifierChain {
+    pub fn new(program: Vec<i64>, topology: AmplifierTopology) -> AmplifierChain {
+        AmplifierChain { program, topology }
+    }
+
+    /// The signal out of the chain with one amplifier per phase setting.
+    pub fn signal(&self, phases: &[i64]) -> Result<i64, AmplifierError> {
+        let topology = match self.topology {
+            AmplifierTopology::Linear => Topology::Chain,
+            AmplifierTopology::Feedback => Topology::Ring,
+        };
+        let mut amplifiers =
+            Network::with_phases(&self.program, phases, topology).expect("chains and rings only link their own machines");
+        if amplifiers.is_empty() {
+            return Err(AmplifierError::NoOutput);
+        }
+        amplifiers.push_input(0, 0);
+        if let NetworkState::Deadlocked { .. } = amplifiers.run()? {
+            return Err(AmplifierError::Deadlocked);
+        }
+        let last = amplifiers.outputs(amplifiers.len() - 1).last();
+        last.copied().ok_or(AmplifierError::NoOutput)
+    }
+
+    /// Try every ordering of the phase settings and return the one with the
+    /// strongest signal, or None if there are no phase settings.
+    pub fn best(&self, phases: &[i64]) -> Result<Option<BestConfiguration>, AmplifierError> {
+        if phases.is_empty() {
+            return Ok(None);
+        }
+        let mut best: Option<BestConfiguration> = None;
+        for phases in Permutations::new(phases) {
+            let signal = self.signal(&phases)?;
+            if best.as_ref().is_none_or(|best| signal > best.signal) {
+                best = Some(BestConfiguration { phases, signal });
+            }
+        }
+        Ok(best)
+    }
+}
@@ -0,0 +1,410 @@
+// Static analysis: what a program does, worked out without running it.
+//
+// Built on the disassembler's CodeMap, this splits the code into basic
+// blocks joined by fall-through and jump edges, and then looks at every
+// parameter given in position mode, which is the only kind whose address is
+// known before running:
+//
+// - writes that land inside an instruction (self-modifying code),
+// - reads and writes past the end of the program, where memory starts as 0,
+//   and which of those reads are of addresses nothing ever writes to,
+// - where the program takes input and produces output.
+//
+// Jumps to a target held in memory (usually returns from a call) can't be
+// followed, and are shown as going to an unknown place. Likewise a parameter
+// that the program overwrites before using is ignored, since its value in
+// the program text isn't the address that ends up being used.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::disasm::{format_instruction, jump_condition, CodeMap};
+use crate::instruction::{Instruction, Mode, OpcodeKind};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Edge {
+    /// On to the next instruction.
+    FallThrough(usize),
+    /// A jump to an immediate target.
+    Jump(usize),
+    /// A jump to a target read from memory.
+    Computed,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// One past the last address of the block.
+    pub end: usize,
+    pub edges: Vec<Edge>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MemoryAccess {
+    /// The instruction making the access.
+    pub at: usize,
+    pub address: usize,
+    pub write: bool,
+}
+
+// A write into the middle of the code.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SelfModification {
+    /// The instruction doing the writing.
+    pub at: usize,
+    pub address: usize,
+    /// The start of the instruction that gets overwritten.
+    pub instruction: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub program_len: usize,
+    pub blocks: Vec<BasicBlock>,
+    pub self_modifying: Vec<SelfModification>,
+    /// Position-mode accesses past the end of the program.
+    pub beyond_program: Vec<MemoryAccess>,
+    /// Reads past the end of the program of addresses nothing writes to in position mode.
+    pub uninitialised_reads: Vec<MemoryAccess>,
+    pub input_sites: Vec<usize>,
+    pub output_sites: Vec<usize>,
+    code: CodeMap,
+}
+
+pub fn analyze(program: &[i64]) -> Analysis {
+    let code = CodeMap::new(program);
+
+    // Every position-mode access, and which instruction covers each code address.
+    // A parameter that the program itself overwrites doesn't say where the
+    // access really goes, so those are left out.
+    let patched: BTreeSet<usize> = code
+        .instructions()
+        .filter(|(_, instruction)| instruction.opcode.writes_memory())
+        .filter(|(_, instruction)| instruction.modes.last() == Some(&Mode::Position))
+        .filter_map(|(_, instruction)| instruction.parameters.last().map(|&address| address as usize))
+        .collect();
+    let mut accesses = Vec::new();
+    let mut owner = BTreeMap::new();
+    for (at, instruction) in code.instructions() {
+        for address in at..at + instruction.width() {
+            owner.insert(address, at);
+        }
+        for (index, &mode) in instruction.modes.iter().enumerate() {
+            let patched = patched.contains(&(at + index + 1));
+            if mode == Mode::Position && instruction.parameters[index] >= 0 && !patched {
+                accesses.push(MemoryAccess {
+                    at,
+                    address: instruction.parameters[index] as usize,
+                    write: instruction.opcode.writes_memory() && index == instruction.parameters.len() - 1,
+                });
+            }
+        }
+    }
+
+    let self_modifying = accesses
+        .iter()
+        .filter(|access| access.write)
+        .filter_map(|access| {
+            owner.get(&access.address).map(|&instruction| SelfModification {
+                at: access.at,
+                address: access.address,
+                instruction,
+            })
+        })
+        .collect();
+    let beyond_program: Vec<MemoryAccess> =
+        accesses.iter().copied().filter(|access| access.address >= program.len()).collect();
+    let written: BTreeSet<usize> = beyond_program.iter().filter(|a| a.write).map(|a| a.address).collect();
+    let uninitialised_reads = beyond_program
+        .iter()
+        .copied()
+        .filter(|access| !access.write && !written.contains(&access.address))
+        .collect();
+    let sites = |opcode| code.instructions().filter(|(_, i)| i.opcode == opcode).map(|(at, _)| at).collect();
+
+    Analysis {
+        program_len: program.len(),
+        blocks: basic_blocks(&code),
+        self_modifying,
+        beyond_program,
+        uninitialised_reads,
+        input_sites: sites(OpcodeKind::Input),
+        output_sites: sites(OpcodeKind::Output),
+        code,
+    }
+}
+
+// Blocks start at address 0, at jump targets, after jumps and after data;
+// they end at a jump or HALT, or just before the next block starts.
+fn basic_blocks(code: &CodeMap) -> Vec<BasicBlock> {
+    let instructions: Vec<(usize, &Instruction)> = code.instructions().collect();
+    let mut blocks = Vec::new();
+    let mut start = None;
+    for (position, &(at, instruction)) in instructions.iter().enumerate() {
+        let start_here = *start.get_or_insert(at);
+        let next = at + instruction.width();
+        let next_starts_block = match instructions.get(position + 1) {
+            Some(&(following, _)) => following != next || code.is_label(following),
+            None => true,
+        };
+
+        let mut edges = Vec::new();
+        let ends_block = match instruction.opcode {
+            OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse => {
+                let (always, never) = jump_condition(instruction);
+                if !never {
+                    edges.push(match instruction.modes[1] {
+                        Mode::Immediate if instruction.parameters[1] >= 0 => Edge::Jump(instruction.parameters[1] as usize),
+                        _ => Edge::Computed,
+                    });
+                }
+                if !always {
+                    edges.push(Edge::FallThrough(next));
+                }
+                true
+            }
+            OpcodeKind::Exit => true,
+            _ => {
+                if next_starts_block {
+                    edges.push(Edge::FallThrough(next));
+                }
+                next_starts_block
+            }
+        };
+        if ends_block {
+            blocks.push(BasicBlock {
+                start: start_here,
+                end: next,
+                edges,
+            });
+            start = None;
+        }
+    }
+    blocks
+}
+
+impl Analysis {
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let instruction_count = self.code.instructions().count();
+        let _ = writeln!(
+            text,
+            "{} values, {} instructions in {} basic blocks",
+            self.program_len,
+            instruction_count,
+            self.blocks.len()
+        );
+
+        let _ = writeln!(text, "\nbasic blocks:");
+        for block in &self.blocks {
+            let edges: Vec<String> = block.edges.iter().map(|edge| describe_edge(*edge)).collect();
+            let _ = writeln!(text, "  {:04}-{:04} -> {}", block.start, block.end - 1, or_none(&edges));
+        }
+
+        let _ = writeln!(text, "\ninput sites: {}", or_none(&addresses(&self.input_sites)));
+        let _ = writeln!(text, "output sites: {}", or_none(&addresses(&self.output_sites)));
+
+        let _ = writeln!(text, "\nself-modifying writes:");
+        if self.self_modifying.is_empty() {
+            let _ = writeln!(text, "  none");
+        }
+        for modification in &self.self_modifying {
+            let _ = writeln!(
+                text,
+                "  {:04} writes [{}], part of the instruction at {:04}",
+                modification.at, modification.address, modification.instruction
+            );
+        }
+
+        let _ = writeln!(text, "\nmemory past the end of the program:");
+        if self.beyond_program.is_empty() {
+            let _ = writeln!(text, "  none");
+        }
+        for access in &self.beyond_program {
+            let action = if access.write { "writes" } else { "reads" };
+            let note = if self.uninitialised_reads.contains(access) {
+                "  (never written: always 0)"
+            } else {
+                ""
+            };
+            let _ = writeln!(text, "  {:04} {} [{}]{}", access.at, action, access.address, note);
+        }
+        text
+    }
+
+    /// The control-flow graph in Graphviz DOT format. Self-modifying writes
+    /// are drawn as red dashed edges from the writer to the block it changes.
+    /// Edges to addresses where no block starts go to an "unknown target" node.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph intcode {{");
+        let _ = writeln!(dot, "    node [shape=box, fontname=\"monospace\"];");
+        let starts: BTreeSet<usize> = self.blocks.iter().map(|block| block.start).collect();
+        let (mut computed, mut unknown) = (false, false);
+        for block in &self.blocks {
+            let mut label = String::new();
+            for (at, instruction) in self.code.instructions().filter(|&(at, _)| block.start <= at && at < block.end) {
+                label.push_str(&format!("{:04}: {}\\l", at, format_instruction(instruction, &self.code)));
+            }
+            let _ = writeln!(dot, "    b{} [label=\"{}\"];", block.start, label.replace('"', "\\\""));
+            for edge in &block.edges {
+                let (to, dashed) = match *edge {
+                    Edge::FallThrough(to) => (to, true),
+                    Edge::Jump(to) => (to, false),
+                    Edge::Computed => {
+                        computed = true;
+                        let _ = writeln!(dot, "    b{} -> computed [style=dotted];", block.start);
+                        continue;
+                    }
+                };
+                if starts.contains(&to) {
+                    let style = if dashed { " [style=dashed]" } else { "" };
+                    let _ = writeln!(dot, "    b{} -> b{}{};", block.start, to, style);
+                } else {
+                    // Into data, the middle of an instruction or past the end: there's no block to point at.
+                    unknown = true;
+                    let style = if dashed { ", style=dashed" } else { "" };
+                    let _ = writeln!(dot, "    b{} -> unknown [label=\"{:04}\"{}];", block.start, to, style);
+                }
+            }
+        }
+        if computed {
+            let _ = writeln!(dot, "    computed [shape=ellipse, label=\"computed jump\"];");
+        }
+        if unknown {
+            let _ = writeln!(dot, "    unknown [shape=ellipse, label=\"unknown target\"];");
+        }
+        // Several writes from one block to the same address make a single edge.
+        let mut drawn = BTreeSet::new();
+        for modification in &self.self_modifying {
+            let (Some(from), Some(to)) = (self.block_of(modification.at), self.block_of(modification.instruction)) else {
+                continue;
+            };
+            if !drawn.insert((from, to, modification.address)) {
+                continue;
+            }
+            let _ = writeln!(
+                dot,
+                "    b{} -> b{} [color=red, style=dashed, label=\"writes {}\"];",
+                from, to, modification.address
+            );
+        }
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+
+    // The start of the block containing an address.
+    fn block_of(&self, address: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .find(|block| block.start <= address && address < block.end)
+            .map(|block| block.start)
+    }
+}
+
+fn describe_edge(edge: Edge) -> String {
+    match edge {
+        Edge::FallThrough(to) => format!("{:04}", to),
+        Edge::Jump(to) => format!("{:04} (jump)", to),
+        Edge::Computed => "? (computed jump)".to_string(),
+    }
+}
+
+fn addresses(list: &[usize]) -> Vec<String> {
+    list.iter().map(|address| format!("{:04}", address)).collect()
+}
+
+fn or_none(items: &[String]) -> String {
+    if items.is_empty() {
+        "none".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_end_at_jumps_and_halts() {
+        // Outputs 0, or the input if it isn't 0.
+        let program = [3, 20, 1005, 20, 9, 104, 0, 99, 0, 4, 20, 99];
+        let analysis = analyze(&program);
+        let blocks = vec![
+            BasicBlock {
+                start: 0,
+                end: 5,
+                edges: vec![Edge::Jump(9), Edge::FallThrough(5)],
+            },
+            BasicBlock {
+                start: 5,
+                end: 8,
+                edges: vec![],
+            },
+            BasicBlock {
+                start: 9,
+                end: 12,
+                edges: vec![],
+            },
+        ];
+        assert_eq!(analysis.blocks, blocks);
+        assert_eq!(analysis.input_sites, vec![0]);
+        assert_eq!(analysis.output_sites, vec![5, 9]);
+
+        // Address 20 is past the end, but written before it is read.
+        assert_eq!(analysis.beyond_program.len(), 3);
+        assert!(analysis.uninitialised_reads.is_empty());
+        assert!(analysis.self_modifying.is_empty());
+    }
+
+    #[test]
+    fn jumps_through_memory_are_computed() {
+        let program = [106, 0, 3, 99];
+        let analysis = analyze(&program);
+        assert_eq!(analysis.blocks[0].edges, vec![Edge::Computed]);
+        assert!(analysis.to_text().contains("0000-0002 -> ? (computed jump)"));
+        assert!(analysis.to_dot().contains("computed [shape=ellipse"));
+    }
+
+    #[test]
+    fn jumps_to_addresses_that_start_no_block_go_to_an_unknown_node() {
+        // The jump lands on the 1 inside the ADD, and the OUT runs off the end.
+        let program = [1101, 1, 1, 20, 1005, 20, 2, 4, 20];
+        let analysis = analyze(&program);
+        assert_eq!(analysis.blocks[0].edges, vec![Edge::Jump(2), Edge::FallThrough(7)]);
+        let dot = analysis.to_dot();
+        assert!(dot.contains("    b0 -> unknown [label=\"0002\"];\n"), "{}", dot);
+        assert!(dot.contains("    b7 -> unknown [label=\"0009\", style=dashed];\n"), "{}", dot);
+        assert_eq!(dot.matches("    unknown [shape=ellipse, label=\"unknown target\"];\n").count(), 1);
+        assert!(!dot.contains("-> b2") && !dot.contains("-> b9"));
+    }
+
+    #[test]
+    fn writes_into_code_are_self_modifying() {
+        // The ADD patches the address the OUT at 4 reads, so that read isn't reported.
+        let program = [1101, 0, 30, 5, 4, 99, 99];
+        let analysis = analyze(&program);
+        let modification = SelfModification {
+            at: 0,
+            address: 5,
+            instruction: 4,
+        };
+        assert_eq!(analysis.self_modifying, vec![modification]);
+        assert!(analysis.beyond_program.is_empty());
+        assert!(analysis.to_dot().contains("b0 -> b0 [color=red, style=dashed, label=\"writes 5\"];"));
+    }
+
+    #[test]
+    fn reads_of_memory_nothing_writes_are_always_zero() {
+        let program = [4, 50, 99];
+        let analysis = analyze(&program);
+        let read = MemoryAccess {
+            at: 0,
+            address: 50,
+            write: false,
+        };
+        assert_eq!(analysis.uninitialised_reads, vec![read]);
+        assert!(analysis.to_text().contains("  0000 reads [50]  (never written: always 0)"));
+    }
+}
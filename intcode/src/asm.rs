@@ -0,0 +1,328 @@
+// Assembler: the reverse of the disassembler.
+//
+//     ; Output the input, doubled.
+//     start:  IN -> [value]
+//             MUL [value], #2 -> [value]
+//             OUT [value]
+//             HALT
+//     value:  .data 0
+//
+// Mnemonics are the ones in the listing (ADD, MUL, ...) or the OpcodeKind
+// names (Add, Multiply, ...), in any case. Operands are `#imm` for immediate
+// mode, `[addr]` for position mode and `[rb+off]` for relative mode. Values
+// can be numbers, labels, or sums of them (`[table+2]`). The `->` before a
+// written parameter is optional; a plain comma works too. A number followed
+// by a colon, as at the start of each disassembly line, is ignored so
+// listings can be assembled again.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::instruction::{Mode, OpcodeKind};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+// A number, a label, or a sum of them.
+#[derive(Debug)]
+struct Expr {
+    terms: Vec<(i64, Term)>,
+}
+
+#[derive(Debug)]
+enum Term {
+    Number(i64),
+    Label(String),
+}
+
+#[derive(Debug)]
+struct Operand {
+    mode: Mode,
+    value: Expr,
+}
+
+#[derive(Debug)]
+enum Item {
+    Instruction { opcode: OpcodeKind, operands: Vec<Operand> },
+    Data(Vec<Expr>),
+}
+
+impl Item {
+    fn width(&self) -> usize {
+        match self {
+            Item::Instruction { operands, .. } => operands.len() + 1,
+            Item::Data(values) => values.len(),
+        }
+    }
+}
+
+/// Assemble source text into a program.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    // First pass: parse every line and work out where the labels are.
+    let mut items: Vec<(usize, Item)> = Vec::new();
+    let mut labels: HashMap<String, i64> = HashMap::new();
+    let mut address = 0;
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| AsmError {
+            line: line_number,
+            message,
+        };
+        let mut rest = line.split(';').next().unwrap().trim();
+
+        while let Some((name, remainder)) = split_label(rest) {
+            let is_address = name.chars().all(|c| c.is_ascii_digit());
+            if !is_address && labels.insert(name.to_string(), address as i64).is_some() {
+                return Err(error(format!("label {} defined twice", name)));
+            }
+            rest = remainder.trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+
+        let item = parse_item(rest).map_err(error)?;
+        address += item.width();
+        items.push((line_number, item));
+    }
+
+    // Second pass: resolve the labels and emit the ints.
+    let mut program = Vec::with_capacity(address);
+    for (line_number, item) in items {
+        let resolve = |expr: &Expr| {
+            evaluate(expr, &labels).map_err(|message| AsmError {
+                line: line_number,
+                message,
+            })
+        };
+        match item {
+            Item::Instruction { opcode, operands } => {
+                let mut opcode_value = opcode.value();
+                let mut scale = 100;
+                for operand in &operands {
+                    opcode_value += operand.mode.value() * scale;
+                    scale *= 10;
+                }
+                program.push(opcode_value);
+                for operand in &operands {
+                    program.push(resolve(&operand.value)?);
+                }
+            }
+            Item::Data(values) => {
+                for value in &values {
+                    program.push(resolve(value)?);
+                }
+            }
+        }
+    }
+    Ok(program)
+}
+
+// Split `name: rest` into the label and the rest of the line.
+fn split_label(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    let name = line[..colon].trim();
+    if is_identifier(name) || (!name.is_empty() && name.chars().all(|c| c.is_ascii_digit())) {
+        Some((name, &line[colon + 1..]))
+    } else {
+        None
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_item(text: &str) -> Result<Item, String> {
+    let (word, operands) = match text.find(char::is_whitespace) {
+        Some(split) => (&text[..split], text[split..].trim()),
+        None => (text, ""),
+    };
+    let operands: Vec<&str> = if operands.is_empty() {
+        Vec::new()
+    } else {
+        operands
+            .split(',')
+            .flat_map(|part| part.split("->"))
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect()
+    };
+
+    if word.starts_with('.') {
+        return match word {
+            ".data" => {
+                let values = operands.iter().map(|value| parse_expr(value)).collect::<Result<_, _>>()?;
+                Ok(Item::Data(values))
+            }
+            _ => Err(format!("unknown directive {}", word)),
+        };
+    }
+
+    let opcode = OpcodeKind::from_mnemonic(word).ok_or(format!("unknown mnemonic {}", word))?;
+    if operands.len() != opcode.parameter_count() {
+        return Err(format!(
+            "{} takes {} operands, found {}",
+            opcode.mnemonic(),
+            opcode.parameter_count(),
+            operands.len()
+        ));
+    }
+    let operands = operands.iter().map(|operand| parse_operand(operand)).collect::<Result<Vec<_>, _>>()?;
+    if opcode.writes_memory() && operands.last().map(|operand| operand.mode) == Some(Mode::Immediate) {
+        return Err(format!("{} can't write to an immediate operand", opcode.mnemonic()));
+    }
+    Ok(Item::Instruction { opcode, operands })
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok(Operand {
+            mode: Mode::Immediate,
+            value: parse_expr(value)?,
+        });
+    }
+    if text.starts_with('[') && text.ends_with(']') {
+        let inner = text[1..text.len() - 1].trim();
+        let lower = inner.to_ascii_lowercase();
+        if lower == "rb" {
+            return Ok(Operand {
+                mode: Mode::Relative,
+                value: parse_expr("0")?,
+            });
+        }
+        if lower.starts_with("rb") && inner[2..].trim_start().starts_with(['+', '-']) {
+            return Ok(Operand {
+                mode: Mode::Relative,
+                value: parse_expr(inner[2..].trim_start())?,
+            });
+        }
+        return Ok(Operand {
+            mode: Mode::Position,
+            value: parse_expr(inner)?,
+        });
+    }
+    Err(format!("bad operand {}", text))
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let text = text.trim();
+    let mut terms = Vec::new();
+    let mut sign = 1;
+    let mut term = String::new();
+    for c in text.chars().chain(std::iter::once('+')) {
+        if (c == '+' || c == '-') && !term.trim().is_empty() {
+            terms.push(parse_term(term.trim(), sign)?);
+            term.clear();
+            sign = if c == '-' { -1 } else { 1 };
+        } else if c == '-' {
+            sign = -sign;
+        } else if c != '+' {
+            term.push(c);
+        }
+    }
+    if terms.is_empty() {
+        return Err(format!("missing value in {:?}", text));
+    }
+    Ok(Expr { terms })
+}
+
+// A number is parsed with its sign, so the most negative i64 can be written.
+fn parse_term(text: &str, sign: i64) -> Result<(i64, Term), String> {
+    let signed = if sign < 0 { format!("-{}", text) } else { text.to_string() };
+    if let Ok(number) = signed.parse() {
+        Ok((1, Term::Number(number)))
+    } else if is_identifier(text) {
+        Ok((sign, Term::Label(text.to_string())))
+    } else {
+        Err(format!("bad value {}", text))
+    }
+}
+
+fn evaluate(expr: &Expr, labels: &HashMap<String, i64>) -> Result<i64, String> {
+    let mut total: i64 = 0;
+    for (sign, term) in &expr.terms {
+        let value = match term {
+            Term::Number(number) => *number,
+            Term::Label(name) => *labels.get(name).ok_or(format!("undefined label {}", name))?,
+        };
+        total = sign
+            .checked_mul(value)
+            .and_then(|value| total.checked_add(value))
+            .ok_or_else(|| "value doesn't fit in an i64".to_string())?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> AsmError {
+        assemble(source).expect_err("source assembled")
+    }
+
+    #[test]
+    fn operands_set_the_mode_digits() {
+        assert_eq!(assemble("ADD #1, [2] -> [rb+3]"), Ok(vec![20101, 1, 2, 3]));
+        assert_eq!(assemble("mul [rb-4], [rb], [5]"), Ok(vec![2202, -4, 0, 5]));
+        assert_eq!(assemble("JumpIfFalse #0, #7\nhalt"), Ok(vec![1106, 0, 7, 99]));
+    }
+
+    #[test]
+    fn labels_and_sums_resolve_to_addresses() {
+        let source = "\
+; Output the input, doubled.
+start:  IN -> [value]
+        MUL [value], #2 -> [value]
+        OUT [value]
+        JT #1, #start+0
+value:  .data 0, value-1, -start
+";
+        assert_eq!(assemble(source), Ok(vec![3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0, 10, 0]));
+        // Listing addresses and several labels on one line are fine.
+        let listing = "0000: a: b: .data a, b, 9223372036854775807, -9223372036854775808";
+        assert_eq!(assemble(listing), Ok(vec![0, 0, i64::MAX, i64::MIN]));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let cases = [
+            (".word 1", "unknown directive .word"),
+            ("NOP", "unknown mnemonic NOP"),
+            ("ADD #1, #2", "ADD takes 3 operands, found 2"),
+            ("IN #5", "IN can't write to an immediate operand"),
+            ("OUT 5", "bad operand 5"),
+            ("OUT #", "missing value in \"\""),
+            ("OUT [5x]", "bad value 5x"),
+            ("OUT [nowhere]", "undefined label nowhere"),
+            ("a: HALT\na: HALT", "label a defined twice"),
+            (".data 9223372036854775807+1", "value doesn't fit in an i64"),
+        ];
+        for (source, message) in cases.iter() {
+            let line = source.lines().count();
+            let expected = AsmError {
+                line,
+                message: message.to_string(),
+            };
+            assert_eq!(error(source), expected, "{}", source);
+        }
+    }
+}
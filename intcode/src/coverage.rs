@@ -0,0 +1,337 @@
+// Code coverage: which parts of a program a set of runs exercised.
+//
+// `Coverage::run_with` runs a machine one step at a time, like
+// `Machine::run_with`, and records how often each address is executed as
+// an instruction, read or written as data, and which way each conditional
+// jump went. Calling it again with other machines (other inputs) adds to
+// the same counts.
+//
+// `to_annotated` prints the disassembly with the counts down the left, in
+// the style of gcov: `#####` for code that never ran, `-` for data. Code
+// the program rewrote before running it is shown as it ran. `to_lcov`
+// writes the counts as an lcov tracefile whose line numbers are those of
+// `disassemble`'s listing, so lcov tools can show them against the output
+// of `intcode disasm`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::disasm::{data_run_end, disassemble, format_instruction, label_name, CodeMap};
+use crate::error::IntcodeError;
+use crate::instruction::{Decoded, Instruction, Mode, OpcodeKind};
+use crate::io::{IntcodeInput, IntcodeOutput};
+use crate::machine::{Machine, RunState};
+
+// How often a conditional jump went each way.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct BranchCoverage {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+impl BranchCoverage {
+    pub fn both_ways(&self) -> bool {
+        self.taken > 0 && self.not_taken > 0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    // Counts by address, so one access far out doesn't need a count for every address below it.
+    executed: BTreeMap<usize, u64>,
+    reads: BTreeMap<usize, u64>,
+    writes: BTreeMap<usize, u64>,
+    // Every different instruction executed at each address, as it was in memory then.
+    instructions: BTreeMap<usize, Vec<Instruction>>,
+    branches: BTreeMap<usize, BranchCoverage>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Run with inputs pulled from `input` and outputs pushed to `output`,
+    /// recording coverage. Stops in the same places as `Machine::run_with`.
+    pub fn run_with<I, O>(&mut self, machine: &mut Machine, input: I, output: O) -> Result<RunState, IntcodeError>
+    where
+        I: IntcodeInput,
+        O: IntcodeOutput,
+    {
+        machine.run_observed(input, output, |machine, cursor, relative_base, instruction| {
+            self.record(machine, cursor, relative_base, instruction)
+        })
+    }
+
+    // Account for one executed instruction. The machine has already run it.
+    fn record(&mut self, machine: &Machine, cursor: usize, relative_base: i64, instruction: &Decoded) {
+        // A jump changes neither memory nor the relative base, so its
+        // condition reads the same now as when it ran.
+        let condition = match instruction.opcode {
+            OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse => machine.read_parameter(instruction, 0).ok(),
+            _ => None,
+        };
+        count(&mut self.executed, cursor);
+        let parameter_count = instruction.opcode.parameter_count();
+        let forms = self.instructions.entry(cursor).or_default();
+        let seen = forms.iter().any(|form| {
+            form.opcode_value == instruction.opcode_value && form.parameters[..] == instruction.parameters[..parameter_count]
+        });
+        if !seen {
+            forms.push(Instruction::from(*instruction));
+        }
+
+        let written = if instruction.opcode.writes_memory() { Some(parameter_count - 1) } else { None };
+        for index in 0..parameter_count {
+            let address = match instruction.modes[index] {
+                Mode::Immediate => continue,
+                Mode::Position => instruction.parameters[index],
+                Mode::Relative => relative_base + instruction.parameters[index],
+            };
+            // A jump target is read like any other parameter.
+            if Some(index) == written {
+                count(&mut self.writes, address as usize);
+            } else {
+                count(&mut self.reads, address as usize);
+            }
+        }
+
+        if let Some(condition) = condition {
+            let branch = self.branches.entry(cursor).or_default();
+            let taken = (condition != 0) == (instruction.opcode == OpcodeKind::JumpIfTrue);
+            if taken {
+                branch.taken += 1;
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+    }
+
+    /// How many times the instruction at `address` was executed.
+    pub fn executed(&self, address: usize) -> u64 {
+        self.executed.get(&address).copied().unwrap_or(0)
+    }
+
+    /// How many times instructions read `address` as data.
+    pub fn reads(&self, address: usize) -> u64 {
+        self.reads.get(&address).copied().unwrap_or(0)
+    }
+
+    /// How many times instructions wrote to `address`.
+    pub fn writes(&self, address: usize) -> u64 {
+        self.writes.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Which ways the conditional jump at `address` went, if it ever ran.
+    pub fn branch(&self, address: usize) -> Option<BranchCoverage> {
+        self.branches.get(&address).copied()
+    }
+
+    /// Every conditional jump that ran, by address.
+    pub fn branches(&self) -> Vec<(usize, BranchCoverage)> {
+        self.branches.iter().map(|(&address, &branch)| (address, branch)).collect()
+    }
+
+    // The instructions of a program, as they last ran where they did,
+    // otherwise as the disassembler finds them. Static instructions
+    // overlapping code that ran are dropped.
+    fn code(&self, program: &[i64], code: &CodeMap) -> BTreeMap<usize, Instruction> {
+        let mut instructions: BTreeMap<usize, Instruction> = self
+            .instructions
+            .iter()
+            .filter_map(|(&address, forms)| forms.last().map(|form| (address, form.clone())))
+            .collect();
+        for (address, instruction) in code.instructions() {
+            let overlaps = (address..address + instruction.width()).any(|a| self.executed(a) > 0);
+            if !overlaps {
+                instructions.insert(address, instruction.clone());
+            }
+        }
+        instructions.retain(|&address, _| address < program.len());
+        instructions
+    }
+
+    /// The disassembly with execution counts, data accesses and branch directions.
+    pub fn to_annotated(&self, program: &[i64]) -> String {
+        let map = CodeMap::new(program);
+        let code = self.code(program, &map);
+        let mut text = String::new();
+
+        let executed = code.keys().filter(|&&address| self.executed(address) > 0).count();
+        let jumps: Vec<usize> = code
+            .iter()
+            .filter(|(_, instruction)| matches!(instruction.opcode, OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse))
+            .map(|(&address, _)| address)
+            .collect();
+        let both_ways = jumps.iter().filter(|&&address| self.branch(address).is_some_and(|b| b.both_ways())).count();
+        let _ = writeln!(text, "; {} of {} instructions executed", executed, code.len());
+        let _ = writeln!(text, "; {} of {} branches went both ways", both_ways, jumps.len());
+
+        let mut address = 0;
+        while address < program.len() {
+            if map.is_label(address) {
+                let _ = writeln!(text, "{:>9}: {}:", "-", label_name(address));
+            }
+            if let Some(instruction) = code.get(&address) {
+                let hits = match self.executed(address) {
+                    0 => "#####".to_string(),
+                    hits => hits.to_string(),
+                };
+                let _ = write!(text, "{:>9}: {:04}: {}", hits, address, format_instruction(instruction, &map));
+                let mut notes = Vec::new();
+                if map.instruction(address) != Some(instruction) {
+                    notes.push("rewritten at run time".to_string());
+                }
+                let forms = self.instructions.get(&address).map_or(&[][..], |forms| &forms[..]);
+                for form in forms.iter().filter(|&form| form != instruction) {
+                    notes.push(format!("also ran as {}", format_instruction(form, &map)));
+                }
+                if let Some(branch) = self.branch(address) {
+                    notes.push(format!("taken {}, not taken {}", branch.taken, branch.not_taken));
+                }
+                if !notes.is_empty() {
+                    let _ = write!(text, " ; {}", notes.join(", "));
+                }
+                text.push('\n');
+                address += instruction.width();
+                continue;
+            }
+
+            if self.reads(address) > 0 || self.writes(address) > 0 {
+                let _ = writeln!(
+                    text,
+                    "{:>9}: {:04}: .data {} ; read {}, written {}",
+                    "-",
+                    address,
+                    program[address],
+                    self.reads(address),
+                    self.writes(address)
+                );
+                address += 1;
+                continue;
+            }
+            // Untouched data runs until the next instruction, label or accessed cell.
+            let end = data_run_end(program, address, |end| {
+                !code.contains_key(&end) && !map.is_label(end) && self.reads(end) == 0 && self.writes(end) == 0
+            });
+            let values: Vec<String> = program[address..end].iter().map(|v| v.to_string()).collect();
+            let _ = writeln!(text, "{:>9}: {:04}: .data {}", "-", address, values.join(", "));
+            address = end;
+        }
+
+        // Memory the program used past its own end, such as a stack.
+        let beyond: BTreeSet<usize> = self
+            .reads
+            .range(program.len()..)
+            .chain(self.writes.range(program.len()..))
+            .map(|(&address, _)| address)
+            .collect();
+        if let (Some(first), Some(last)) = (beyond.first(), beyond.last()) {
+            let reads: u64 = beyond.iter().map(|&address| self.reads(address)).sum();
+            let writes: u64 = beyond.iter().map(|&address| self.writes(address)).sum();
+            let _ = writeln!(
+                text,
+                "; {} cells past the end of the program used, between {} and {}: read {}, written {}",
+                beyond.len(),
+                first,
+                last,
+                reads,
+                writes
+            );
+        }
+        text
+    }
+
+    /// An lcov tracefile for `source`, a file holding `disassemble(program)`.
+    pub fn to_lcov(&self, program: &[i64], source: &str) -> String {
+        let code = CodeMap::new(program);
+        let mut text = String::new();
+        let _ = writeln!(text, "TN:");
+        let _ = writeln!(text, "SF:{}", source);
+        let (mut lines_found, mut lines_hit) = (0, 0);
+        let (mut branches_found, mut branches_hit) = (0, 0);
+        for (line, start, end) in listing_lines(program) {
+            let is_code = code.instruction(start).is_some();
+            let hits: u64 = (start..end).map(|address| self.executed(address)).sum();
+            if !is_code && hits == 0 {
+                continue;
+            }
+            lines_found += 1;
+            if hits > 0 {
+                lines_hit += 1;
+            }
+            let _ = writeln!(text, "DA:{},{}", line, hits);
+
+            let jump = match code.instruction(start) {
+                Some(instruction) => matches!(instruction.opcode, OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse),
+                None => false,
+            };
+            if !jump {
+                continue;
+            }
+            branches_found += 2;
+            match self.branch(start) {
+                Some(branch) => {
+                    branches_hit += (branch.taken > 0) as u32 + (branch.not_taken > 0) as u32;
+                    let _ = writeln!(text, "BRDA:{},0,0,{}", line, branch.taken);
+                    let _ = writeln!(text, "BRDA:{},0,1,{}", line, branch.not_taken);
+                }
+                None => {
+                    let _ = writeln!(text, "BRDA:{},0,0,-", line);
+                    let _ = writeln!(text, "BRDA:{},0,1,-", line);
+                }
+            }
+        }
+        let _ = writeln!(text, "BRF:{}", branches_found);
+        let _ = writeln!(text, "BRH:{}", branches_hit);
+        let _ = writeln!(text, "LF:{}", lines_found);
+        let _ = writeln!(text, "LH:{}", lines_hit);
+        text.push_str("end_of_record\n");
+        text
+    }
+}
+
+fn count(counts: &mut BTreeMap<usize, u64>, address: usize) {
+    *counts.entry(address).or_insert(0) += 1;
+}
+
+// (line number, first address, one past the last address) for each line of
+// the disassembly that holds code or data, numbering lines from 1.
+fn listing_lines(program: &[i64]) -> Vec<(usize, usize, usize)> {
+    let mut lines: Vec<(usize, usize, usize)> = Vec::new();
+    for (index, line) in disassemble(program).lines().enumerate() {
+        let address = line.split(':').next().and_then(|address| address.parse::<usize>().ok());
+        if let Some(address) = address {
+            if let Some(previous) = lines.last_mut() {
+                previous.2 = address;
+            }
+            lines.push((index + 1, address, program.len()));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn accesses_far_past_the_program_are_counted() {
+        // ARB #10000000; ADD #1, #2 -> [rb]; OUT [rb]; HALT
+        let program = vec![109, 10_000_000, 21101, 1, 2, 0, 204, 0, 99];
+        let mut machine = Machine::new(program.clone());
+        let mut coverage = Coverage::new();
+        let mut outputs = Vec::new();
+        assert_eq!(coverage.run_with(&mut machine, VecDeque::new(), &mut outputs), Ok(RunState::Halted));
+        assert_eq!(outputs, vec![3]);
+
+        assert_eq!((coverage.writes(10_000_000), coverage.reads(10_000_000)), (1, 1));
+        assert_eq!(coverage.executed(6), 1);
+        assert_eq!(coverage.reads.len() + coverage.writes.len(), 2);
+        let annotated = coverage.to_annotated(&program);
+        assert!(annotated.starts_with("; 4 of 4 instructions executed\n"), "{}", annotated);
+        let beyond = "; 1 cells past the end of the program used, between 10000000 and 10000000: read 1, written 1\n";
+        assert!(annotated.ends_with(beyond), "{}", annotated);
+    }
+}
@@ -0,0 +1,513 @@
+// Step debugger for Intcode programs.
+//
+// The debugger wraps a Machine and understands a small set of text commands.
+// It doesn't do any I/O itself: `execute` takes one command line and returns
+// the text to show, so the REPL (`intcode debug <file>`) is just a loop
+// around it.
+//
+// Every instruction it runs, and every poke, is recorded in a `History`, so it can also run
+// backwards: to undo a few steps, to go back to the instruction that last
+// wrote some address, or to the one that produced an output.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use crate::disasm::{format_instruction, CodeMap};
+use crate::history::History;
+use crate::instruction::{Instruction, OpcodeKind};
+use crate::machine::{Machine, RunState};
+
+const HELP: &str = "\
+commands:
+  step [n]            execute n instructions (default 1)
+  continue            run until a breakpoint, watchpoint, input request or halt
+  reverse-step [n]    undo n instructions (default 1)
+  reverse-continue [addr]
+                      run backwards to a breakpoint or watchpoint, or with addr
+                      to just before the last instruction that wrote to addr
+  when-output [n]     go back to the instruction that produced output n,
+                      counting from 1 (default: the latest output)
+  break <addr|op>     stop before the instruction at addr, or before any op (e.g. OUT)
+  delete <addr|op>    remove a breakpoint
+  watch <addr>        stop when the value at addr changes
+  unwatch <addr>      remove a watchpoint
+  info                show cursor, relative base, breakpoints and the next instruction
+  print <addr> [n]    show n memory cells starting at addr
+  poke <addr> <value> write a value into memory
+  input <value>...    queue input values
+  list [addr] [n]     decode n instructions starting at addr (default: the cursor)
+  save <file>         write a snapshot of the machine to a file
+  load <file>         replace the machine with one from a snapshot file
+  restart             reload the program
+  quit                leave the debugger
+An empty line repeats the last command.";
+
+// The most cells `print` shows, or instructions `list` decodes, at once.
+const MAX_COUNT: usize = 1000;
+
+pub struct Debugger {
+    program: Vec<i64>,
+    machine: Machine,
+    history: History,
+    code: CodeMap,
+    breakpoints: BTreeSet<usize>,
+    opcode_breakpoints: Vec<OpcodeKind>,
+    // Watched address -> value when last checked.
+    watchpoints: BTreeMap<usize, i64>,
+    last_command: String,
+}
+
+// Why a run of instructions stopped.
+enum Stop {
+    Breakpoint,
+    Watchpoint(usize, i64, i64),
+    State(RunState),
+    Error(String),
+    // Running backwards, there was nothing more to undo.
+    StartOfHistory,
+}
+
+impl Debugger {
+    pub fn new(program: Vec<i64>) -> Debugger {
+        Debugger {
+            machine: Machine::new(program.clone()),
+            history: History::new(),
+            code: CodeMap::new(&program),
+            program,
+            breakpoints: BTreeSet::new(),
+            opcode_breakpoints: Vec::new(),
+            watchpoints: BTreeMap::new(),
+            last_command: String::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn help() -> &'static str {
+        HELP
+    }
+
+    /// Run one command line and return what to show the user.
+    pub fn execute(&mut self, line: &str) -> String {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            line.trim().to_string()
+        };
+        self.last_command = line.clone();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return String::new(),
+        };
+        let result = match command {
+            "step" | "s" => self.step(args),
+            "continue" | "c" => Ok(self.continue_running()),
+            "reverse-step" | "rs" => self.reverse_step(args),
+            "reverse-continue" | "rc" => self.reverse_continue(args),
+            "when-output" => self.when_output(args),
+            "break" | "b" => self.add_breakpoint(args),
+            "delete" | "d" => self.delete_breakpoint(args),
+            "watch" | "w" => self.watch(args),
+            "unwatch" => self.unwatch(args),
+            "info" | "i" => Ok(self.info()),
+            "print" | "p" => self.print(args),
+            "poke" => self.poke(args),
+            "input" => self.input(args),
+            "list" | "l" => self.list(args),
+            "save" => self.save(args),
+            "load" => self.load(args),
+            "restart" => {
+                self.machine = Machine::new(self.program.clone());
+                self.history.clear();
+                self.refresh_watchpoints();
+                Ok(format!("restarted\n{}", self.current()))
+            }
+            "help" | "h" => Ok(HELP.to_string()),
+            _ => Err(format!("unknown command {} (try help)", command)),
+        };
+        match result {
+            Ok(text) => text,
+            Err(message) => format!("error: {}", message),
+        }
+    }
+
+    fn step(&mut self, args: &[&str]) -> Result<String, String> {
+        let count = match args.first() {
+            Some(count) => count.parse().map_err(|_| format!("bad count {}", count))?,
+            None => 1,
+        };
+        let mut text = String::new();
+        for _ in 0..count {
+            let stop = self.step_once(&mut text);
+            if let Some(stop) = stop {
+                self.describe_stop(stop, &mut text);
+                return Ok(text);
+            }
+        }
+        text.push_str(&self.current());
+        Ok(text)
+    }
+
+    fn continue_running(&mut self) -> String {
+        let mut text = String::new();
+        // Always execute the first instruction, so we can continue from a breakpoint.
+        let mut stop = self.step_once(&mut text);
+        while stop.is_none() {
+            if self.at_breakpoint() {
+                stop = Some(Stop::Breakpoint);
+            } else {
+                stop = self.step_once(&mut text);
+            }
+        }
+        self.describe_stop(stop.unwrap(), &mut text);
+        text
+    }
+
+    // Execute one instruction, noting any output. Returns why we must stop, if we must.
+    fn step_once(&mut self, text: &mut String) -> Option<Stop> {
+        match self.history.step(&mut self.machine) {
+            Err(e) => return Some(Stop::Error(e.to_string())),
+            Ok(Some(RunState::Output(value))) => {
+                let _ = writeln!(text, "output: {}", value);
+            }
+            Ok(Some(state)) => return Some(Stop::State(state)),
+            Ok(None) => {}
+        }
+        for (&address, last) in self.watchpoints.iter_mut() {
+            let value = self.machine.read(address);
+            if value != *last {
+                let old = *last;
+                *last = value;
+                return Some(Stop::Watchpoint(address, old, value));
+            }
+        }
+        None
+    }
+
+    fn reverse_step(&mut self, args: &[&str]) -> Result<String, String> {
+        let count = match args.first() {
+            Some(count) => count.parse().map_err(|_| format!("bad count {}", count))?,
+            None => 1,
+        };
+        let mut text = String::new();
+        for _ in 0..count {
+            if self.history.step_back(&mut self.machine).is_none() {
+                text.push_str("at the start of the history\n");
+                break;
+            }
+        }
+        self.refresh_watchpoints();
+        let _ = writeln!(text, "step {}", self.machine.steps());
+        text.push_str(&self.current());
+        Ok(text)
+    }
+
+    fn reverse_continue(&mut self, args: &[&str]) -> Result<String, String> {
+        if !args.is_empty() {
+            let address = parse_address(args.first())?;
+            let index = self.history.last_write_index(address).ok_or(format!("no recorded write to [{}]", address))?;
+            let change = *self.history.changes().nth(index).expect("index is in the history");
+            self.rewind_to(index + 1);
+            let written = self.machine.read(address);
+            self.rewind_to(index);
+            let (_, old) = change.write.unwrap_or_default();
+            return Ok(format!(
+                "step {}: [{}] {} -> {}\n{}",
+                change.step,
+                address,
+                old,
+                written,
+                self.current()
+            ));
+        }
+        let mut text = String::new();
+        // Always undo one instruction, so we can go back from a breakpoint.
+        let mut stop = self.step_back_once();
+        while stop.is_none() {
+            if self.at_breakpoint() {
+                stop = Some(Stop::Breakpoint);
+            } else {
+                stop = self.step_back_once();
+            }
+        }
+        self.describe_stop(stop.unwrap(), &mut text);
+        Ok(text)
+    }
+
+    fn when_output(&mut self, args: &[&str]) -> Result<String, String> {
+        let n = match args.first() {
+            Some(n) => n.parse().map_err(|_| format!("bad output number {}", n))?,
+            None if self.history.outputs() == 0 => return Err("no outputs yet".to_string()),
+            None => self.history.outputs(),
+        };
+        let index = self.history.output_index(n).ok_or(format!("output {} isn't in the history", n))?;
+        let change = *self.history.changes().nth(index).expect("index is in the history");
+        self.rewind_to(index);
+        Ok(format!(
+            "output {} ({}) was produced at step {}\n{}",
+            n,
+            change.output.unwrap_or_default(),
+            change.step,
+            self.current()
+        ))
+    }
+
+    // Undo instructions and pokes until only the oldest `len` recorded ones are left.
+    // Pokes don't execute anything, so the step count can't tell them apart.
+    fn rewind_to(&mut self, len: usize) {
+        self.history.rewind(&mut self.machine, len);
+        self.refresh_watchpoints();
+    }
+
+    // Undo one instruction. Returns why we must stop, if we must.
+    fn step_back_once(&mut self) -> Option<Stop> {
+        if self.history.step_back(&mut self.machine).is_none() {
+            return Some(Stop::StartOfHistory);
+        }
+        for (&address, last) in self.watchpoints.iter_mut() {
+            let value = self.machine.read(address);
+            if value != *last {
+                let old = *last;
+                *last = value;
+                return Some(Stop::Watchpoint(address, old, value));
+            }
+        }
+        None
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        if self.breakpoints.contains(&self.machine.cursor()) {
+            return true;
+        }
+        match self.machine.current_instruction() {
+            Ok(instruction) => self.opcode_breakpoints.contains(&instruction.opcode),
+            Err(_) => false,
+        }
+    }
+
+    fn describe_stop(&self, stop: Stop, text: &mut String) {
+        match stop {
+            Stop::Breakpoint => text.push_str("breakpoint\n"),
+            Stop::Watchpoint(address, old, new) => {
+                let _ = writeln!(text, "watchpoint: [{}] {} -> {}", address, old, new);
+            }
+            Stop::State(RunState::NeedsInput) => text.push_str("waiting for input (use `input <value>`)\n"),
+            Stop::State(RunState::Halted) => text.push_str("halted\n"),
+            Stop::State(RunState::Output(_)) => {}
+            Stop::Error(message) => {
+                let _ = writeln!(text, "error: {}", message);
+            }
+            Stop::StartOfHistory => {
+                let _ = writeln!(text, "at the start of the history (step {})", self.machine.steps());
+            }
+        }
+        text.push_str(&self.current());
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let target = args.first().ok_or("break needs an address or opcode")?;
+        match target.parse::<usize>() {
+            Ok(address) => {
+                self.breakpoints.insert(address);
+                Ok(format!("breakpoint at {:04}", address))
+            }
+            Err(_) => {
+                let opcode = OpcodeKind::from_mnemonic(target).ok_or(format!("unknown opcode {}", target))?;
+                if !self.opcode_breakpoints.contains(&opcode) {
+                    self.opcode_breakpoints.push(opcode);
+                }
+                Ok(format!("breakpoint on {}", opcode.mnemonic()))
+            }
+        }
+    }
+
+    fn delete_breakpoint(&mut self, args: &[&str]) -> Result<String, String> {
+        let target = args.first().ok_or("delete needs an address or opcode")?;
+        let removed = match target.parse::<usize>() {
+            Ok(address) => self.breakpoints.remove(&address),
+            Err(_) => {
+                let opcode = OpcodeKind::from_mnemonic(target).ok_or(format!("unknown opcode {}", target))?;
+                let before = self.opcode_breakpoints.len();
+                self.opcode_breakpoints.retain(|&kind| kind != opcode);
+                self.opcode_breakpoints.len() != before
+            }
+        };
+        if removed {
+            Ok(format!("deleted breakpoint {}", target))
+        } else {
+            Err(format!("no breakpoint {}", target))
+        }
+    }
+
+    fn watch(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = parse_address(args.first())?;
+        self.watchpoints.insert(address, self.machine.read(address));
+        Ok(format!("watching [{}] = {}", address, self.machine.read(address)))
+    }
+
+    fn unwatch(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = parse_address(args.first())?;
+        match self.watchpoints.remove(&address) {
+            Some(_) => Ok(format!("stopped watching [{}]", address)),
+            None => Err(format!("not watching [{}]", address)),
+        }
+    }
+
+    fn refresh_watchpoints(&mut self) {
+        for (&address, value) in self.watchpoints.iter_mut() {
+            *value = self.machine.read(address);
+        }
+    }
+
+    fn info(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "cursor:        {}", self.machine.cursor());
+        let _ = writeln!(text, "relative base: {}", self.machine.relative_base());
+        let _ = writeln!(text, "steps:         {}", self.machine.steps());
+        let _ = writeln!(text, "history:       {} steps", self.history.len());
+        let _ = writeln!(text, "queued inputs: {}", self.machine.pending_inputs());
+        let _ = writeln!(text, "halted:        {}", self.machine.is_halted());
+        if !self.breakpoints.is_empty() || !self.opcode_breakpoints.is_empty() {
+            let mut names: Vec<String> = self.breakpoints.iter().map(|a| format!("{:04}", a)).collect();
+            names.extend(self.opcode_breakpoints.iter().map(|o| o.mnemonic().to_string()));
+            let _ = writeln!(text, "breakpoints:   {}", names.join(", "));
+        }
+        if !self.watchpoints.is_empty() {
+            let watched: Vec<String> = self.watchpoints.iter().map(|(a, v)| format!("[{}] = {}", a, v)).collect();
+            let _ = writeln!(text, "watchpoints:   {}", watched.join(", "));
+        }
+        if let Ok(instruction) = self.machine.current_instruction() {
+            let _ = writeln!(text, "instruction:   {:?}", instruction);
+            for index in 0..instruction.parameters.len() {
+                let _ = writeln!(text, "  {}", self.describe_parameter(&instruction, index));
+            }
+        }
+        text.push_str(&self.current());
+        text
+    }
+
+    // `[rb+3] = [2003] = 42` for one parameter of an instruction.
+    fn describe_parameter(&self, instruction: &Instruction, index: usize) -> String {
+        let formatted = instruction.format_parameter(index);
+        match self.machine.resolve_parameter(instruction, index) {
+            Ok((Some(address), value)) => format!("{} = [{}] = {}", formatted, address, value),
+            Ok((None, value)) => format!("{} = {}", formatted, value),
+            Err(e) => format!("{} = error: {}", formatted, e),
+        }
+    }
+
+    fn print(&self, args: &[&str]) -> Result<String, String> {
+        let address = parse_address(args.first())?;
+        let count = parse_count(args.get(1), 1)?;
+        let end = address.checked_add(count).ok_or(format!("address {} is out of range", address))?;
+        let values: Vec<String> = (address..end).map(|a| self.machine.read(a).to_string()).collect();
+        Ok(format!("{:04}: {}", address, values.join(", ")))
+    }
+
+    fn poke(&mut self, args: &[&str]) -> Result<String, String> {
+        let address = parse_address(args.first())?;
+        let value = args.get(1).ok_or("poke needs a value")?;
+        let value: i64 = value.parse().map_err(|_| format!("bad value {}", value))?;
+        self.history.write(&mut self.machine, address, value).map_err(|e| e.to_string())?;
+        self.refresh_watchpoints();
+        Ok(format!("[{}] = {}", address, value))
+    }
+
+    fn input(&mut self, args: &[&str]) -> Result<String, String> {
+        if args.is_empty() {
+            return Err("input needs at least one value".to_string());
+        }
+        for value in args {
+            let value = value.parse().map_err(|_| format!("bad value {}", value))?;
+            self.machine.push_input(value);
+        }
+        Ok(format!("{} input(s) queued", self.machine.pending_inputs()))
+    }
+
+    fn save(&self, args: &[&str]) -> Result<String, String> {
+        let filename = args.first().ok_or("save needs a file name")?;
+        let file = File::create(filename).map_err(|e| e.to_string())?;
+        self.machine.save(BufWriter::new(file)).map_err(|e| e.to_string())?;
+        Ok(format!("saved to {}", filename))
+    }
+
+    fn load(&mut self, args: &[&str]) -> Result<String, String> {
+        let filename = args.first().ok_or("load needs a file name")?;
+        let file = File::open(filename).map_err(|e| e.to_string())?;
+        self.machine = Machine::load(BufReader::new(file)).map_err(|e| e.to_string())?;
+        self.history.clear();
+        self.refresh_watchpoints();
+        Ok(format!("loaded {}\n{}", filename, self.current()))
+    }
+
+    fn list(&self, args: &[&str]) -> Result<String, String> {
+        let mut address = match args.first() {
+            Some(_) => parse_address(args.first())?,
+            None => self.machine.cursor(),
+        };
+        let count = parse_count(args.get(1), 10)?;
+        let mut text = String::new();
+        for _ in 0..count {
+            let width = match Instruction::new(self.machine.memory(), address) {
+                Ok(instruction) => {
+                    let _ = writeln!(text, "{}", self.format_line(address, &instruction));
+                    instruction.width()
+                }
+                Err(_) => {
+                    let _ = writeln!(text, "{:04}: .data {}", address, self.machine.read(address));
+                    1
+                }
+            };
+            // Stop at the end of the address space.
+            address = match address.checked_add(width) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        Ok(text)
+    }
+
+    fn format_line(&self, address: usize, instruction: &Instruction) -> String {
+        let marker = if address == self.machine.cursor() { "=>" } else { "  " };
+        let flag = if self.breakpoints.contains(&address) { "*" } else { " " };
+        format!(
+            "{}{}{:04}: {}",
+            marker,
+            flag,
+            address,
+            format_instruction(instruction, &self.code)
+        )
+    }
+
+    // The instruction about to run.
+    fn current(&self) -> String {
+        if self.machine.is_halted() {
+            return String::new();
+        }
+        match self.machine.current_instruction() {
+            Ok(instruction) => format!("{}\n", self.format_line(self.machine.cursor(), &instruction)),
+            Err(e) => format!("error: {}\n", e),
+        }
+    }
+}
+
+fn parse_address(arg: Option<&&str>) -> Result<usize, String> {
+    let arg = arg.ok_or("missing address")?;
+    arg.parse().map_err(|_| format!("bad address {}", arg))
+}
+
+fn parse_count(arg: Option<&&str>, default: usize) -> Result<usize, String> {
+    let count = match arg {
+        Some(arg) => arg.parse().map_err(|_| format!("bad count {}", arg))?,
+        None => default,
+    };
+    if count > MAX_COUNT {
+        return Err(format!("count {} is more than {}", count, MAX_COUNT));
+    }
+    Ok(count)
+}
@@ -0,0 +1,436 @@
+// Peripherals: devices a program drives through its outputs and asks for input.
+//
+// Day 11's robot and day 13's arcade cabinet both read a program's outputs a
+// few values at a time as commands and answer its requests for input. A
+// `Device` does one of those jobs; a `Bus` runs a machine with devices
+// attached, collecting outputs into commands for the device that takes them
+// and offering each input request to the devices in turn.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::error::IntcodeError;
+use crate::machine::{Machine, RunState};
+
+pub trait Device {
+    /// How many output values make up one command; 0 if the device takes no output.
+    fn command_len(&self) -> usize {
+        0
+    }
+
+    /// Carry out one command of `command_len` values.
+    fn command(&mut self, values: &[i64]) -> Result<(), DeviceError> {
+        Err(DeviceError::BadCommand {
+            values: values.to_vec(),
+            reason: "device takes no output",
+        })
+    }
+
+    /// Answer a request for input, or None to leave it to the next device.
+    fn input(&mut self) -> Option<i64> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DeviceError {
+    Machine(IntcodeError),
+    /// A device couldn't make sense of a command.
+    BadCommand { values: Vec<i64>, reason: &'static str },
+    /// The program output a value but no device takes output.
+    NoOutputDevice { value: i64 },
+    /// The program halted part way through a command.
+    IncompleteCommand { values: Vec<i64> },
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceError::Machine(error) => write!(f, "{}", error),
+            DeviceError::BadCommand { values, reason } => write!(f, "bad command {:?}: {}", values, reason),
+            DeviceError::NoOutputDevice { value } => write!(f, "output {} with no device to take it", value),
+            DeviceError::IncompleteCommand { values } => write!(f, "halted part way through command {:?}", values),
+        }
+    }
+}
+
+impl Error for DeviceError {}
+
+impl From<IntcodeError> for DeviceError {
+    fn from(error: IntcodeError) -> DeviceError {
+        DeviceError::Machine(error)
+    }
+}
+
+// Outputs go to the first attached device that takes output. Requests for
+// input are offered to each device in the order they were attached until one
+// answers.
+#[derive(Default)]
+pub struct Bus<'a> {
+    devices: Vec<&'a mut dyn Device>,
+    // Outputs for the command that hasn't been completed yet.
+    pending: Vec<i64>,
+}
+
+impl<'a> Bus<'a> {
+    pub fn new() -> Bus<'a> {
+        Bus {
+            devices: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn attach(mut self, device: &'a mut dyn Device) -> Bus<'a> {
+        self.devices.push(device);
+        self
+    }
+
+    /// The outputs of the command still being collected.
+    pub fn pending(&self) -> &[i64] {
+        &self.pending
+    }
+
+    /// Replace the partly collected command, as when rewinding the machine sending it.
+    pub fn set_pending(&mut self, values: Vec<i64>) {
+        self.pending = values;
+    }
+
+    /// Run until the program halts, or return `NeedsInput` if no device answers its request for input.
+    pub fn run(&mut self, machine: &mut Machine) -> Result<RunState, DeviceError> {
+        loop {
+            match machine.resume()? {
+                RunState::NeedsInput => match self.devices.iter_mut().find_map(|device| device.input()) {
+                    Some(value) => machine.push_input(value),
+                    None => return Ok(RunState::NeedsInput),
+                },
+                RunState::Output(value) => self.output(value)?,
+                RunState::Halted if self.pending.is_empty() => return Ok(RunState::Halted),
+                RunState::Halted => {
+                    return Err(DeviceError::IncompleteCommand {
+                        values: self.pending.clone(),
+                    })
+                }
+            }
+        }
+    }
+
+    fn output(&mut self, value: i64) -> Result<(), DeviceError> {
+        let device = match self.devices.iter_mut().find(|device| device.command_len() > 0) {
+            Some(device) => device,
+            None => return Err(DeviceError::NoOutputDevice { value }),
+        };
+        self.pending.push(value);
+        if self.pending.len() == device.command_len() {
+            let result = device.command(&self.pending);
+            self.pending.clear();
+            result?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Tile {
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+}
+
+impl Tile {
+    pub fn from_value(value: i64) -> Option<Tile> {
+        match value {
+            0 => Some(Tile::Empty),
+            1 => Some(Tile::Wall),
+            2 => Some(Tile::Block),
+            3 => Some(Tile::Paddle),
+            4 => Some(Tile::Ball),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> char {
+        match self {
+            Tile::Empty => ' ',
+            Tile::Wall => '=',
+            Tile::Block => '#',
+            Tile::Paddle => '^',
+            Tile::Ball => '0',
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScreenCommand {
+    Draw { x: i64, y: i64, tile: Tile },
+    /// Sent as a draw at (-1, 0).
+    Score(i64),
+}
+
+impl ScreenCommand {
+    pub fn decode(values: &[i64]) -> Result<ScreenCommand, DeviceError> {
+        let bad = |reason| DeviceError::BadCommand {
+            values: values.to_vec(),
+            reason,
+        };
+        match *values {
+            [-1, 0, score] => Ok(ScreenCommand::Score(score)),
+            [x, y, tile] if x >= 0 && y >= 0 => match Tile::from_value(tile) {
+                Some(tile) => Ok(ScreenCommand::Draw { x, y, tile }),
+                None => Err(bad("unknown tile")),
+            },
+            [_, _, _] => Err(bad("tile off the screen")),
+            _ => Err(bad("screen commands are three values")),
+        }
+    }
+}
+
+// Day 13's arcade screen: takes (x, y, tile) triples, and the score.
+#[derive(Debug, Clone, Default)]
+pub struct TileScreen {
+    tiles: HashMap<(i64, i64), Tile>,
+    score: Option<i64>,
+}
+
+impl TileScreen {
+    pub fn new() -> TileScreen {
+        TileScreen::default()
+    }
+
+    pub fn tile(&self, x: i64, y: i64) -> Tile {
+        self.tiles.get(&(x, y)).copied().unwrap_or(Tile::Empty)
+    }
+
+    pub fn count(&self, tile: Tile) -> usize {
+        self.tiles.values().filter(|&&t| t == tile).count()
+    }
+
+    /// Where a tile is, if it's on the screen; for the ball and paddle, of which there's one each.
+    pub fn find(&self, tile: Tile) -> Option<(i64, i64)> {
+        self.tiles.iter().find(|(_, &t)| t == tile).map(|(&position, _)| position)
+    }
+
+    pub fn score(&self) -> Option<i64> {
+        self.score
+    }
+
+    /// The screen as text, one line per row.
+    pub fn render(&self) -> String {
+        let width = self.tiles.keys().map(|&(x, _)| x + 1).max().unwrap_or(0);
+        let height = self.tiles.keys().map(|&(_, y)| y + 1).max().unwrap_or(0);
+        let mut text = String::new();
+        for y in 0..height {
+            text.extend((0..width).map(|x| self.tile(x, y).symbol()));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl Device for TileScreen {
+    fn command_len(&self) -> usize {
+        3
+    }
+
+    fn command(&mut self, values: &[i64]) -> Result<(), DeviceError> {
+        match ScreenCommand::decode(values)? {
+            ScreenCommand::Draw { x, y, tile } => {
+                self.tiles.insert((x, y), tile);
+            }
+            ScreenCommand::Score(score) => self.score = Some(score),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Tilt {
+    Left,
+    Neutral,
+    Right,
+}
+
+impl Tilt {
+    pub fn value(self) -> i64 {
+        match self {
+            Tilt::Left => -1,
+            Tilt::Neutral => 0,
+            Tilt::Right => 1,
+        }
+    }
+}
+
+// Day 13's joystick: answers each request for input with a tilt from `source`.
+pub struct Joystick<'a> {
+    source: Box<dyn FnMut() -> Option<Tilt> + 'a>,
+    moves: usize,
+}
+
+impl<'a> Joystick<'a> {
+    pub fn new<F: FnMut() -> Option<Tilt> + 'a>(source: F) -> Joystick<'a> {
+        Joystick {
+            source: Box::new(source),
+            moves: 0,
+        }
+    }
+
+    /// Tilts the joystick each way in turn, then leaves requests unanswered.
+    pub fn scripted<I>(moves: I) -> Joystick<'a>
+    where
+        I: IntoIterator<Item = Tilt>,
+        I::IntoIter: 'a,
+    {
+        let mut moves = moves.into_iter();
+        Joystick::new(move || moves.next())
+    }
+
+    /// How many requests for input the joystick has answered.
+    pub fn moves(&self) -> usize {
+        self.moves
+    }
+}
+
+impl Device for Joystick<'_> {
+    fn input(&mut self) -> Option<i64> {
+        let tilt = (self.source)()?;
+        self.moves += 1;
+        Some(tilt.value())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Color {
+    Black,
+    White,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Turn {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PaintCommand {
+    pub color: Color,
+    pub turn: Turn,
+}
+
+impl PaintCommand {
+    pub fn decode(values: &[i64]) -> Result<PaintCommand, DeviceError> {
+        let bad = |reason| DeviceError::BadCommand {
+            values: values.to_vec(),
+            reason,
+        };
+        let (color, turn) = match *values {
+            [color, turn] => (color, turn),
+            _ => return Err(bad("paint commands are two values")),
+        };
+        let color = match color {
+            0 => Color::Black,
+            1 => Color::White,
+            _ => return Err(bad("unknown color")),
+        };
+        let turn = match turn {
+            0 => Turn::Left,
+            1 => Turn::Right,
+            _ => return Err(bad("unknown turn")),
+        };
+        Ok(PaintCommand { color, turn })
+    }
+}
+
+// Day 11's hull painting robot: reports the color of the panel it's on,
+// then paints it and turns and moves one panel as it's told.
+// The robot starts at (0, 0) facing up, with y increasing upwards.
+#[derive(Debug, Clone)]
+pub struct PaintRobot {
+    panels: HashMap<(i64, i64), Color>,
+    position: (i64, i64),
+    direction: (i64, i64),
+}
+
+impl Default for PaintRobot {
+    fn default() -> PaintRobot {
+        PaintRobot::new()
+    }
+}
+
+impl PaintRobot {
+    /// A robot on an all black hull.
+    pub fn new() -> PaintRobot {
+        PaintRobot {
+            panels: HashMap::new(),
+            position: (0, 0),
+            direction: (0, 1),
+        }
+    }
+
+    /// A robot standing on a panel of the given color.
+    pub fn starting_on(color: Color) -> PaintRobot {
+        let mut robot = PaintRobot::new();
+        robot.panels.insert((0, 0), color);
+        robot
+    }
+
+    pub fn color(&self, x: i64, y: i64) -> Color {
+        self.panels.get(&(x, y)).copied().unwrap_or(Color::Black)
+    }
+
+    /// How many panels the robot has painted at least once.
+    pub fn painted(&self) -> usize {
+        self.panels.len()
+    }
+
+    pub fn count(&self, color: Color) -> usize {
+        self.panels.values().filter(|&&c| c == color).count()
+    }
+
+    pub fn position(&self) -> (i64, i64) {
+        self.position
+    }
+
+    /// The painted part of the hull as text, top row first, white panels as '#'.
+    pub fn render(&self) -> String {
+        let xs = self.panels.keys().map(|&(x, _)| x);
+        let ys = self.panels.keys().map(|&(_, y)| y);
+        let (left, right) = (xs.clone().min().unwrap_or(0), xs.max().unwrap_or(0));
+        let (bottom, top) = (ys.clone().min().unwrap_or(0), ys.max().unwrap_or(0));
+        let mut text = String::new();
+        for y in (bottom..=top).rev() {
+            text.extend((left..=right).map(|x| match self.color(x, y) {
+                Color::Black => ' ',
+                Color::White => '#',
+            }));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl Device for PaintRobot {
+    fn command_len(&self) -> usize {
+        2
+    }
+
+    fn command(&mut self, values: &[i64]) -> Result<(), DeviceError> {
+        let PaintCommand { color, turn } = PaintCommand::decode(values)?;
+        self.panels.insert(self.position, color);
+        let (dx, dy) = self.direction;
+        self.direction = match turn {
+            Turn::Left => (-dy, dx),
+            Turn::Right => (dy, -dx),
+        };
+        self.position = (self.position.0 + self.direction.0, self.position.1 + self.direction.1);
+        Ok(())
+    }
+
+    fn input(&mut self) -> Option<i64> {
+        match self.color(self.position.0, self.position.1) {
+            Color::Black => Some(0),
+            Color::White => Some(1),
+        }
+    }
+}
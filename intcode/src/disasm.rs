@@ -0,0 +1,358 @@
+// Disassembler: turns a program back into a readable listing.
+//
+//     0000: ADD #1, #2 -> [100]
+//     L0004:
+//     0004: JT [100], #L0004
+//     0007: HALT
+//     0008: .data 30, 40, 50
+//
+// Code is found by following the program from address 0: straight-line
+// instructions, jump targets given as immediates, and return addresses
+// pushed before a call (an immediate that lands just after an
+// unconditional jump). Code that is only reached through computed jumps
+// is picked up by sweeping the gaps left over: a run of at least two
+// instructions inside a gap that ends in a HALT or unconditional jump is
+// taken to be code. Everything else is shown as data, including opcodes
+// with more mode digits than parameters, so the listing assembles back to
+// the same program.
+
+use std::collections::BTreeSet;
+
+use crate::instruction::{Instruction, Mode, OpcodeKind};
+use crate::memory::Memory;
+
+pub(crate) const DATA_PER_LINE: usize = 8;
+
+// Which addresses of a program hold instructions, and where they jump to.
+#[derive(Debug, Clone)]
+pub struct CodeMap {
+    // The decoded instruction starting at each address, if any.
+    instructions: Vec<Option<Instruction>>,
+    // Addresses that are jump targets or return addresses.
+    labels: BTreeSet<usize>,
+}
+
+impl CodeMap {
+    pub fn new(program: &[i64]) -> CodeMap {
+        let memory = Memory::from(program);
+        let mut instructions: Vec<Option<Instruction>> = vec![None; program.len()];
+        let mut covered = vec![false; program.len()];
+        let mut labels = BTreeSet::new();
+        // Immediately after an unconditional jump: a possible return address.
+        let mut after_jumps = BTreeSet::new();
+        let mut constants = BTreeSet::new();
+        let mut pending = vec![0];
+
+        loop {
+            while let Some(start) = pending.pop() {
+                let mut cursor = start;
+                while cursor < program.len() && !covered[cursor] {
+                    let instruction = match decode(&memory, cursor) {
+                        Some(instruction) if cursor + instruction.width() <= program.len() => instruction,
+                        _ => break,
+                    };
+                    if (cursor..cursor + instruction.width()).any(|address| covered[address]) {
+                        break;
+                    }
+                    for covered in &mut covered[cursor..cursor + instruction.width()] {
+                        *covered = true;
+                    }
+                    let next = cursor + instruction.width();
+                    let mut falls_through = true;
+                    match instruction.opcode {
+                        OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse => {
+                            let (taken, never_taken) = jump_condition(&instruction);
+                            if instruction.modes[1] == Mode::Immediate && !never_taken {
+                                let target = instruction.parameters[1];
+                                if target >= 0 && (target as usize) < program.len() {
+                                    labels.insert(target as usize);
+                                    pending.push(target as usize);
+                                }
+                            }
+                            if taken {
+                                falls_through = false;
+                                after_jumps.insert(next);
+                            }
+                        }
+                        OpcodeKind::Add | OpcodeKind::Multiply => {
+                            if let Some(value) = constant_result(&instruction) {
+                                constants.insert(value);
+                            }
+                        }
+                        OpcodeKind::Exit => falls_through = false,
+                        _ => {}
+                    }
+                    instructions[cursor] = Some(instruction);
+                    if !falls_through {
+                        break;
+                    }
+                    cursor = next;
+                }
+            }
+
+            // Return addresses: constants stored somewhere that point just
+            // past an unconditional jump we haven't decoded yet.
+            for &address in after_jumps.iter() {
+                if constants.contains(&(address as i64)) && address < program.len() && !covered[address] {
+                    labels.insert(address);
+                    pending.push(address);
+                }
+            }
+            if pending.is_empty() {
+                pending = sweep_gaps(&memory, &covered);
+            }
+            if pending.is_empty() {
+                break;
+            }
+        }
+
+        // A jump into the middle of another instruction doesn't get a label.
+        labels.retain(|&address| instructions[address].is_some());
+        CodeMap { instructions, labels }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    /// The instruction starting at this address, if it is code.
+    pub fn instruction(&self, address: usize) -> Option<&Instruction> {
+        self.instructions.get(address).and_then(|i| i.as_ref())
+    }
+
+    pub fn is_label(&self, address: usize) -> bool {
+        self.labels.contains(&address)
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = usize> + '_ {
+        self.labels.iter().copied()
+    }
+
+    /// Every instruction in address order.
+    pub fn instructions(&self) -> impl Iterator<Item = (usize, &Instruction)> {
+        self.instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(address, i)| i.as_ref().map(|i| (address, i)))
+    }
+}
+
+// The first address in each uncovered gap that starts a run of at least
+// two instructions ending in a HALT or an unconditional jump.
+fn sweep_gaps(memory: &Memory, covered: &[bool]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut gap_start = 0;
+    while gap_start < covered.len() {
+        if covered[gap_start] {
+            gap_start += 1;
+            continue;
+        }
+        let mut end = gap_start;
+        while end < covered.len() && !covered[end] {
+            end += 1;
+        }
+        if let Some(start) = (gap_start..end).find(|&start| is_code_run(memory, start, end)) {
+            starts.push(start);
+        }
+        gap_start = end;
+    }
+    starts
+}
+
+fn is_code_run(memory: &Memory, start: usize, end: usize) -> bool {
+    let mut cursor = start;
+    let mut count = 0;
+    while let Some(instruction) = decode(memory, cursor) {
+        if cursor + instruction.width() > end {
+            return false;
+        }
+        count += 1;
+        let terminates = match instruction.opcode {
+            OpcodeKind::Exit => true,
+            OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse => jump_condition(&instruction).0,
+            _ => false,
+        };
+        if terminates {
+            return count >= 2;
+        }
+        cursor += instruction.width();
+    }
+    false
+}
+
+// The instruction at `cursor`, unless its opcode has stray mode digits
+// (`10104` for `104`): listing that as code would assemble to another value.
+fn decode(memory: &Memory, cursor: usize) -> Option<Instruction> {
+    Instruction::new(memory, cursor)
+        .ok()
+        .filter(|instruction| instruction.opcode_value == instruction.canonical_value())
+}
+
+pub fn label_name(address: usize) -> String {
+    format!("L{:04}", address)
+}
+
+// (always taken, never taken) for a jump whose condition is an immediate.
+pub(crate) fn jump_condition(instruction: &Instruction) -> (bool, bool) {
+    if instruction.modes[0] != Mode::Immediate {
+        return (false, false);
+    }
+    let condition = instruction.parameters[0] != 0;
+    let taken = match instruction.opcode {
+        OpcodeKind::JumpIfTrue => condition,
+        _ => !condition,
+    };
+    (taken, !taken)
+}
+
+// The value an ADD or MUL of two immediates stores.
+fn constant_result(instruction: &Instruction) -> Option<i64> {
+    if instruction.modes[0] != Mode::Immediate || instruction.modes[1] != Mode::Immediate {
+        return None;
+    }
+    let (a, b) = (instruction.parameters[0], instruction.parameters[1]);
+    match instruction.opcode {
+        OpcodeKind::Add => a.checked_add(b),
+        _ => a.checked_mul(b),
+    }
+}
+
+/// Produce a listing of the whole program.
+pub fn disassemble(program: &[i64]) -> String {
+    let code = CodeMap::new(program);
+    let mut listing = String::new();
+    let mut address = 0;
+    while address < program.len() {
+        if code.is_label(address) {
+            listing.push_str(&format!("{}:\n", label_name(address)));
+        }
+        match code.instruction(address) {
+            Some(instruction) => {
+                listing.push_str(&format!("{:04}: {}\n", address, format_instruction(instruction, &code)));
+                address += instruction.width();
+            }
+            None => {
+                // Data runs until the next instruction or label.
+                let end = data_run_end(program, address, |end| code.instruction(end).is_none() && !code.is_label(end));
+                let values: Vec<String> = program[address..end].iter().map(|v| v.to_string()).collect();
+                listing.push_str(&format!("{:04}: .data {}\n", address, values.join(", ")));
+                address = end;
+            }
+        }
+    }
+    listing
+}
+
+// One past the last value of the line of data starting at `address`: up to
+// `DATA_PER_LINE` values, ending before the first address `is_data` rejects.
+pub(crate) fn data_run_end(program: &[i64], address: usize, mut is_data: impl FnMut(usize) -> bool) -> usize {
+    let mut end = address + 1;
+    while end < program.len() && end - address < DATA_PER_LINE && is_data(end) {
+        end += 1;
+    }
+    end
+}
+
+/// Format an instruction, naming immediate jump targets and return addresses by label.
+pub fn format_instruction(instruction: &Instruction, code: &CodeMap) -> String {
+    let parameters: Vec<String> = (0..instruction.parameters.len())
+        .map(|index| {
+            let parameter = instruction.parameters[index];
+            let names_code = match instruction.opcode {
+                OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse => index == 1,
+                OpcodeKind::Add | OpcodeKind::Multiply => index < 2 && constant_result(instruction) == Some(parameter),
+                _ => false,
+            };
+            if instruction.modes[index] == Mode::Immediate
+                && names_code
+                && parameter >= 0
+                && code.is_label(parameter as usize)
+            {
+                format!("#{}", label_name(parameter as usize))
+            } else {
+                instruction.format_parameter(index)
+            }
+        })
+        .collect();
+    instruction.format_with(&parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing_labels_jump_targets_and_groups_data() {
+        let program = [1101, 1, 2, 100, 1005, 100, 4, 99, 30, 40, 50];
+        let expected = "\
+0000: ADD #1, #2 -> [100]
+L0004:
+0004: JT [100], #L0004
+0007: HALT
+0008: .data 30, 40, 50
+";
+        assert_eq!(disassemble(&program), expected);
+    }
+
+    #[test]
+    fn data_is_split_into_lines() {
+        let mut program = vec![99];
+        program.extend(1..=20);
+        let listing = disassemble(&program);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "0001: .data 1, 2, 3, 4, 5, 6, 7, 8");
+        assert_eq!(lines[3], "0017: .data 17, 18, 19, 20");
+
+        assert_eq!(data_run_end(&program, 1, |_| true), 1 + DATA_PER_LINE);
+        assert_eq!(data_run_end(&program, 1, |end| end != 3), 3);
+        assert_eq!(data_run_end(&program, 19, |_| true), 21);
+    }
+
+    #[test]
+    fn return_addresses_pushed_before_a_call_are_code() {
+        // Store the return address 7, jump to a routine at 9 that jumps back through it.
+        let program = [1101, 0, 7, 13, 1105, 1, 9, 99, 0, 1106, 0, 13, 99, 0];
+        let code = CodeMap::new(&program);
+        assert!(code.is_label(7));
+        assert!(code.is_label(9));
+        assert_eq!(code.instruction(7).map(|i| i.opcode), Some(OpcodeKind::Exit));
+        assert!(disassemble(&program).starts_with("0000: ADD #0, #L0007 -> [13]\n"));
+    }
+
+    #[test]
+    fn gaps_holding_runs_of_code_are_swept_up() {
+        // Nothing jumps to 4 directly, but it starts two instructions ending in a
+        // jump. A lone HALT at 3 isn't enough, and 9 onwards is data.
+        let program = [1106, 0, 11, 99, 104, 5, 1105, 1, 0, 0, 0, 4];
+        let code = CodeMap::new(&program);
+        assert!(code.instruction(3).is_none());
+        assert_eq!(code.instruction(4).map(|i| i.opcode), Some(OpcodeKind::Output));
+        assert_eq!(code.instruction(6).map(|i| i.opcode), Some(OpcodeKind::JumpIfTrue));
+        assert!(code.instruction(9).is_none());
+        assert_eq!(code.instructions().count(), 3);
+    }
+
+    #[test]
+    fn opcodes_with_stray_mode_digits_are_data() {
+        for program in [vec![10104, 5, 99], vec![1199, 3], vec![104, 1, 100001, 1, 2, 3, 99]].iter() {
+            let listing = disassemble(program);
+            assert_eq!(crate::asm::assemble(&listing).as_ref(), Ok(program), "{}", listing);
+        }
+        assert_eq!(disassemble(&[10104, 5, 99]), "0000: .data 10104, 5, 99\n");
+        assert!(disassemble(&[104, 1, 100001, 1, 2, 3, 99]).starts_with("0000: OUT #1\n0002: .data 100001"));
+    }
+
+    #[test]
+    fn jumps_into_an_instruction_get_no_label() {
+        let program = [1101, 1, 1, 20, 1105, 1, 2];
+        let code = CodeMap::new(&program);
+        assert!(!code.is_label(2));
+        assert!(code.instruction(2).is_none());
+        assert!(disassemble(&program).contains("0004: JT #1, #2\n"));
+    }
+}
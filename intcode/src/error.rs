@@ -0,0 +1,103 @@
+// Everything that can go wrong while running an Intcode program.
+// Each error records the cursor and the raw opcode value of the
+// instruction that was being executed when it happened.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IntcodeError {
+    UnknownOpcode {
+        cursor: usize,
+        opcode_value: i64,
+    },
+    InvalidMode {
+        cursor: usize,
+        opcode_value: i64,
+        mode: i64,
+    },
+    WriteInImmediateMode {
+        cursor: usize,
+        opcode_value: i64,
+    },
+    NegativeAddress {
+        cursor: usize,
+        opcode_value: i64,
+        address: i64,
+    },
+    MemoryLimitExceeded {
+        cursor: usize,
+        opcode_value: i64,
+        address: usize,
+        limit: usize,
+    },
+    InputExhausted {
+        cursor: usize,
+        opcode_value: i64,
+    },
+    StepLimitExceeded {
+        cursor: usize,
+        opcode_value: i64,
+        limit: u64,
+    },
+    DeadlineExceeded {
+        cursor: usize,
+        opcode_value: i64,
+    },
+    /// A result or an address didn't fit in an i64.
+    Overflow {
+        cursor: usize,
+        opcode_value: i64,
+    },
+}
+
+impl IntcodeError {
+    pub fn cursor(&self) -> usize {
+        match *self {
+            IntcodeError::UnknownOpcode { cursor, .. }
+            | IntcodeError::InvalidMode { cursor, .. }
+            | IntcodeError::WriteInImmediateMode { cursor, .. }
+            | IntcodeError::NegativeAddress { cursor, .. }
+            | IntcodeError::MemoryLimitExceeded { cursor, .. }
+            | IntcodeError::InputExhausted { cursor, .. }
+            | IntcodeError::StepLimitExceeded { cursor, .. }
+            | IntcodeError::DeadlineExceeded { cursor, .. }
+            | IntcodeError::Overflow { cursor, .. } => cursor,
+        }
+    }
+
+    pub fn opcode_value(&self) -> i64 {
+        match *self {
+            IntcodeError::UnknownOpcode { opcode_value, .. }
+            | IntcodeError::InvalidMode { opcode_value, .. }
+            | IntcodeError::WriteInImmediateMode { opcode_value, .. }
+            | IntcodeError::NegativeAddress { opcode_value, .. }
+            | IntcodeError::MemoryLimitExceeded { opcode_value, .. }
+            | IntcodeError::InputExhausted { opcode_value, .. }
+            | IntcodeError::StepLimitExceeded { opcode_value, .. }
+            | IntcodeError::DeadlineExceeded { opcode_value, .. }
+            | IntcodeError::Overflow { opcode_value, .. } => opcode_value,
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IntcodeError::UnknownOpcode { .. } => write!(f, "unknown opcode")?,
+            IntcodeError::InvalidMode { mode, .. } => write!(f, "invalid parameter mode {}", mode)?,
+            IntcodeError::WriteInImmediateMode { .. } => write!(f, "write to an immediate mode parameter")?,
+            IntcodeError::NegativeAddress { address, .. } => write!(f, "access to negative address {}", address)?,
+            IntcodeError::MemoryLimitExceeded { address, limit, .. } => {
+                write!(f, "write to address {} beyond memory limit {}", address, limit)?
+            }
+            IntcodeError::InputExhausted { .. } => write!(f, "input requested but none available")?,
+            IntcodeError::StepLimitExceeded { limit, .. } => write!(f, "step limit of {} exceeded", limit)?,
+            IntcodeError::DeadlineExceeded { .. } => write!(f, "deadline passed")?,
+            IntcodeError::Overflow { .. } => write!(f, "arithmetic overflow")?,
+        }
+        write!(f, " (opcode {} at cursor {})", self.opcode_value(), self.cursor())
+    }
+}
+
+impl Error for IntcodeError {}
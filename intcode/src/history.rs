@@ -0,0 +1,202 @@
+// Reverse execution: an undo log of the instructions a machine has run.
+//
+// `History::step` runs one instruction and records what it changed: the
+// cursor, the relative base, the memory cell it overwrote and the input it
+// consumed. `step_back` puts all of that back, so a machine can be rewound
+// one instruction at a time and then run forwards again, getting the same
+// inputs the second time round. Values poked into memory with `write` are
+// recorded too, and undone like an instruction.
+
+use std::collections::VecDeque;
+
+use crate::error::IntcodeError;
+use crate::instruction::OpcodeKind;
+use crate::machine::{Machine, RunState};
+
+// Changes kept before the oldest are forgotten; about 80MB.
+const DEFAULT_HISTORY_LIMIT: usize = 1_000_000;
+
+/// What one instruction changed, as it was before the instruction ran.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Change {
+    /// The machine's step count before the instruction.
+    pub step: u64,
+    pub cursor: usize,
+    pub relative_base: i64,
+    /// The address written and the value it held before.
+    pub write: Option<(usize, i64)>,
+    pub input: Option<i64>,
+    pub output: Option<i64>,
+    /// Whether the machine had halted, which it can only have before a poke.
+    pub halted: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    changes: VecDeque<Change>,
+    limit: usize,
+    // Outputs produced by the recorded instructions, including forgotten ones.
+    outputs: usize,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
+
+impl History {
+    pub fn new() -> History {
+        History::with_limit(DEFAULT_HISTORY_LIMIT)
+    }
+
+    /// Keep only the last `limit` instructions.
+    pub fn with_limit(limit: usize) -> History {
+        History {
+            changes: VecDeque::new(),
+            limit,
+            outputs: 0,
+        }
+    }
+
+    /// Execute one instruction on `machine` as `Machine::step` does, recording it.
+    pub fn step(&mut self, machine: &mut Machine) -> Result<Option<RunState>, IntcodeError> {
+        if machine.is_halted() {
+            return machine.step();
+        }
+        let instruction = machine.current_instruction()?;
+        let mut change = Change {
+            step: machine.steps(),
+            cursor: machine.cursor(),
+            relative_base: machine.relative_base(),
+            write: None,
+            input: None,
+            output: None,
+            halted: false,
+        };
+        if instruction.opcode == OpcodeKind::Input {
+            change.input = machine.inputs.front().copied();
+        }
+        if instruction.opcode.writes_memory() {
+            let index = instruction.opcode.parameter_count() - 1;
+            if let (Some(address), value) = machine.resolve_parameter(&instruction, index)? {
+                change.write = Some((address, value));
+            }
+        }
+        let state = machine.step()?;
+        match state {
+            // Nothing was executed.
+            Some(RunState::NeedsInput) => return Ok(state),
+            Some(RunState::Output(value)) => {
+                change.output = Some(value);
+                self.outputs += 1;
+            }
+            _ => {}
+        }
+        self.record(change);
+        Ok(state)
+    }
+
+    /// Poke a value into `machine`'s memory as `Machine::try_write` does, recording it.
+    pub fn write(&mut self, machine: &mut Machine, address: usize, value: i64) -> Result<(), IntcodeError> {
+        let old = machine.read(address);
+        machine.try_write(address, value)?;
+        self.record(Change {
+            step: machine.steps(),
+            cursor: machine.cursor(),
+            relative_base: machine.relative_base(),
+            write: Some((address, old)),
+            input: None,
+            output: None,
+            halted: machine.is_halted(),
+        });
+        Ok(())
+    }
+
+    fn record(&mut self, change: Change) {
+        if self.changes.len() == self.limit {
+            self.changes.pop_front();
+        }
+        if self.limit > 0 {
+            self.changes.push_back(change);
+        }
+    }
+
+    /// Undo the last recorded instruction or poke, returning what it changed.
+    pub fn step_back(&mut self, machine: &mut Machine) -> Option<Change> {
+        let change = self.changes.pop_back()?;
+        if let Some((address, value)) = change.write {
+            machine.write(address, value);
+        }
+        if let Some(input) = change.input {
+            machine.inputs.push_front(input);
+        }
+        if change.output.is_some() {
+            self.outputs -= 1;
+        }
+        machine.cursor = change.cursor;
+        machine.relative_base = change.relative_base;
+        machine.steps = change.step;
+        machine.halted = change.halted;
+        Some(change)
+    }
+
+    /// Number of instructions and pokes that can be undone.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Forget everything, e.g. after the machine has been replaced.
+    pub fn clear(&mut self) {
+        self.changes.clear();
+        self.outputs = 0;
+    }
+
+    /// The recorded changes, oldest first.
+    pub fn changes(&self) -> impl Iterator<Item = &Change> {
+        self.changes.iter()
+    }
+
+    /// Undo changes until only the oldest `len` are left.
+    pub fn rewind(&mut self, machine: &mut Machine, len: usize) {
+        while self.changes.len() > len && self.step_back(machine).is_some() {}
+    }
+
+    /// The most recent recorded instruction or poke that wrote to `address`.
+    pub fn last_write(&self, address: usize) -> Option<&Change> {
+        self.last_write_index(address).map(|index| &self.changes[index])
+    }
+
+    // Where `last_write` is in the history, counting from the oldest change.
+    pub(crate) fn last_write_index(&self, address: usize) -> Option<usize> {
+        self.changes.iter().rposition(|change| matches!(change.write, Some((a, _)) if a == address))
+    }
+
+    /// Number of outputs produced by the recorded instructions.
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// The instruction that produced the nth output, counting from 1, if it's still recorded.
+    pub fn output(&self, n: usize) -> Option<&Change> {
+        self.output_index(n).map(|index| &self.changes[index])
+    }
+
+    // Where `output` is in the history, counting from the oldest change.
+    pub(crate) fn output_index(&self, n: usize) -> Option<usize> {
+        let mut number = self.outputs;
+        for (index, change) in self.changes.iter().enumerate().rev() {
+            if change.output.is_some() {
+                if number == n {
+                    return Some(index);
+                }
+                number -= 1;
+            }
+        }
+        None
+    }
+}
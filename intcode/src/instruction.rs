@@ -0,0 +1,275 @@
+// Decoding of a single Intcode instruction.
+
+use crate::error::IntcodeError;
+use crate::memory::Memory;
+use std::fmt;
+
+// Mapping of opcodes to instructions.
+// Each opcode has a name and an associated
+// number of parameters
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum OpcodeKind {
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    IsLessThan,
+    IsEquals,
+    AdjustRelativeBase,
+    Exit,
+}
+
+impl OpcodeKind {
+    pub const ALL: [OpcodeKind; 10] = [
+        OpcodeKind::Add,
+        OpcodeKind::Multiply,
+        OpcodeKind::Input,
+        OpcodeKind::Output,
+        OpcodeKind::JumpIfTrue,
+        OpcodeKind::JumpIfFalse,
+        OpcodeKind::IsLessThan,
+        OpcodeKind::IsEquals,
+        OpcodeKind::AdjustRelativeBase,
+        OpcodeKind::Exit,
+    ];
+
+    /// Look up the opcode from the last two digits of an instruction.
+    pub fn from_value(value: i64) -> Option<OpcodeKind> {
+        match value {
+            1 => Some(OpcodeKind::Add),
+            2 => Some(OpcodeKind::Multiply),
+            3 => Some(OpcodeKind::Input),
+            4 => Some(OpcodeKind::Output),
+            5 => Some(OpcodeKind::JumpIfTrue),
+            6 => Some(OpcodeKind::JumpIfFalse),
+            7 => Some(OpcodeKind::IsLessThan),
+            8 => Some(OpcodeKind::IsEquals),
+            9 => Some(OpcodeKind::AdjustRelativeBase),
+            99 => Some(OpcodeKind::Exit),
+            _ => None,
+        }
+    }
+
+    /// The numeric opcode, as it appears in the last two digits.
+    pub fn value(self) -> i64 {
+        match self {
+            OpcodeKind::Add => 1,
+            OpcodeKind::Multiply => 2,
+            OpcodeKind::Input => 3,
+            OpcodeKind::Output => 4,
+            OpcodeKind::JumpIfTrue => 5,
+            OpcodeKind::JumpIfFalse => 6,
+            OpcodeKind::IsLessThan => 7,
+            OpcodeKind::IsEquals => 8,
+            OpcodeKind::AdjustRelativeBase => 9,
+            OpcodeKind::Exit => 99,
+        }
+    }
+
+    /// Short name used in disassembly listings.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            OpcodeKind::Add => "ADD",
+            OpcodeKind::Multiply => "MUL",
+            OpcodeKind::Input => "IN",
+            OpcodeKind::Output => "OUT",
+            OpcodeKind::JumpIfTrue => "JT",
+            OpcodeKind::JumpIfFalse => "JF",
+            OpcodeKind::IsLessThan => "LT",
+            OpcodeKind::IsEquals => "EQ",
+            OpcodeKind::AdjustRelativeBase => "ARB",
+            OpcodeKind::Exit => "HALT",
+        }
+    }
+
+    /// Look up an opcode by its mnemonic (`MUL`) or variant name (`Multiply`),
+    /// ignoring case.
+    pub fn from_mnemonic(name: &str) -> Option<OpcodeKind> {
+        OpcodeKind::ALL.iter().copied().find(|kind| {
+            name.eq_ignore_ascii_case(kind.mnemonic()) || name.eq_ignore_ascii_case(&format!("{:?}", kind))
+        })
+    }
+
+    /// True if the last parameter is the address the result is written to.
+    pub fn writes_memory(self) -> bool {
+        matches!(
+            self,
+            OpcodeKind::Add | OpcodeKind::Multiply | OpcodeKind::IsLessThan | OpcodeKind::IsEquals | OpcodeKind::Input
+        )
+    }
+
+    /// Number of parameters following the opcode.
+    pub fn parameter_count(self) -> usize {
+        match self {
+            OpcodeKind::Add | OpcodeKind::Multiply => 3,
+            OpcodeKind::IsLessThan | OpcodeKind::IsEquals => 3,
+            OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse => 2,
+            OpcodeKind::Input | OpcodeKind::Output => 1,
+            OpcodeKind::AdjustRelativeBase => 1,
+            OpcodeKind::Exit => 0,
+        }
+    }
+}
+
+// Parameter modes. The hundreds digit of the instruction gives the mode of
+// the first parameter, the thousands digit the second, and so on.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl Mode {
+    pub fn from_value(value: i64) -> Option<Mode> {
+        match value {
+            0 => Some(Mode::Position),
+            1 => Some(Mode::Immediate),
+            2 => Some(Mode::Relative),
+            _ => None,
+        }
+    }
+
+    pub fn value(self) -> i64 {
+        match self {
+            Mode::Position => 0,
+            Mode::Immediate => 1,
+            Mode::Relative => 2,
+        }
+    }
+}
+
+// An instruction decoded without allocating, as the machine executes it.
+// Unused parameter slots hold position mode and 0.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Decoded {
+    pub opcode_value: i64,
+    pub opcode: OpcodeKind,
+    pub modes: [Mode; 3],
+    pub parameters: [i64; 3],
+}
+
+impl Decoded {
+    /* Read the opcode at the cursor.
+    The last two digits are the opcode, the remaining digits
+    (read right to left) are the parameter modes.
+    Missing mode digits are position mode.
+    */
+    pub fn new(memory: &Memory, cursor: usize) -> Result<Decoded, IntcodeError> {
+        let opcode_value = memory.get(cursor);
+        let opcode = OpcodeKind::from_value(opcode_value % 100).ok_or(IntcodeError::UnknownOpcode {
+            cursor,
+            opcode_value,
+        })?;
+
+        let mut decoded = Decoded {
+            opcode_value,
+            opcode,
+            modes: [Mode::Position; 3],
+            parameters: [0; 3],
+        };
+        let mut mode_digits = opcode_value / 100;
+        for index in 0..opcode.parameter_count() {
+            decoded.modes[index] = Mode::from_value(mode_digits % 10).ok_or(IntcodeError::InvalidMode {
+                cursor,
+                opcode_value,
+                mode: mode_digits % 10,
+            })?;
+            decoded.parameters[index] = memory.get(cursor.saturating_add(index + 1));
+            mode_digits /= 10;
+        }
+        Ok(decoded)
+    }
+
+    /// Total width of the instruction in memory, including the opcode.
+    pub fn width(&self) -> usize {
+        self.opcode.parameter_count() + 1
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Instruction {
+    pub opcode_value: i64,
+    pub opcode: OpcodeKind,
+    pub modes: Vec<Mode>,
+    pub parameters: Vec<i64>,
+}
+
+impl Instruction {
+    pub fn new(memory: &Memory, cursor: usize) -> Result<Instruction, IntcodeError> {
+        Decoded::new(memory, cursor).map(Instruction::from)
+    }
+
+    /// Total width of the instruction in memory, including the opcode.
+    pub fn width(&self) -> usize {
+        self.parameters.len() + 1
+    }
+
+    /// The opcode value this instruction would be assembled to. Differs from
+    /// `opcode_value` if the original had stray mode digits.
+    pub fn canonical_value(&self) -> i64 {
+        let mut value = self.opcode.value();
+        let mut scale = 100;
+        for mode in &self.modes {
+            value += mode.value() * scale;
+            scale *= 10;
+        }
+        value
+    }
+
+    /// Format one parameter in listing syntax: `#5`, `[100]` or `[rb+3]`.
+    pub fn format_parameter(&self, index: usize) -> String {
+        let parameter = self.parameters[index];
+        match self.modes[index] {
+            Mode::Position => format!("[{}]", parameter),
+            Mode::Immediate => format!("#{}", parameter),
+            Mode::Relative if parameter < 0 => format!("[rb{}]", parameter),
+            Mode::Relative => format!("[rb+{}]", parameter),
+        }
+    }
+
+    /// Lay out already formatted parameters as `OP a, b -> c`.
+    pub fn format_with(&self, parameters: &[String]) -> String {
+        let mnemonic = self.opcode.mnemonic();
+        if parameters.is_empty() {
+            return mnemonic.to_string();
+        }
+        let (sources, target) = if self.opcode.writes_memory() {
+            let (target, sources) = parameters.split_last().unwrap();
+            (sources, Some(target))
+        } else {
+            (parameters, None)
+        };
+        let mut line = mnemonic.to_string();
+        if !sources.is_empty() {
+            line.push(' ');
+            line.push_str(&sources.join(", "));
+        }
+        if let Some(target) = target {
+            line.push_str(" -> ");
+            line.push_str(target);
+        }
+        line
+    }
+}
+
+impl From<Decoded> for Instruction {
+    fn from(decoded: Decoded) -> Instruction {
+        let count = decoded.opcode.parameter_count();
+        Instruction {
+            opcode_value: decoded.opcode_value,
+            opcode: decoded.opcode,
+            modes: decoded.modes[..count].to_vec(),
+            parameters: decoded.parameters[..count].to_vec(),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parameters: Vec<String> = (0..self.parameters.len()).map(|i| self.format_parameter(i)).collect();
+        write!(f, "{}", self.format_with(&parameters))
+    }
+}
@@ -0,0 +1,269 @@
+// Sources of input and sinks for output that a Machine can be wired up to.
+//
+// `Machine::run_with` pulls from an `IntcodeInput` whenever the program asks
+// for input and pushes every output into an `IntcodeOutput`, so the same
+// program can be driven by a test vector, a terminal or another machine.
+
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// How often a blocked ChannelInput checks whether it has been told to shut down.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(10);
+
+pub trait IntcodeInput {
+    /// The next input value, or None if there is nothing more to give.
+    fn read(&mut self) -> Option<i64>;
+}
+
+pub trait IntcodeOutput {
+    fn write(&mut self, value: i64);
+}
+
+impl<T: IntcodeInput + ?Sized> IntcodeInput for &mut T {
+    fn read(&mut self) -> Option<i64> {
+        (**self).read()
+    }
+}
+
+impl<T: IntcodeOutput + ?Sized> IntcodeOutput for &mut T {
+    fn write(&mut self, value: i64) {
+        (**self).write(value)
+    }
+}
+
+// Queues: inputs are taken from the front, outputs appended to the back.
+impl IntcodeInput for VecDeque<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+impl IntcodeOutput for VecDeque<i64> {
+    fn write(&mut self, value: i64) {
+        self.push_back(value);
+    }
+}
+
+impl IntcodeOutput for Vec<i64> {
+    fn write(&mut self, value: i64) {
+        self.push(value);
+    }
+}
+
+// Closures, wrapped so they don't clash with the impls above.
+pub struct FnInput<F>(pub F);
+
+impl<F: FnMut() -> Option<i64>> IntcodeInput for FnInput<F> {
+    fn read(&mut self) -> Option<i64> {
+        (self.0)()
+    }
+}
+
+pub struct FnOutput<F>(pub F);
+
+impl<F: FnMut(i64)> IntcodeOutput for FnOutput<F> {
+    fn write(&mut self, value: i64) {
+        (self.0)(value)
+    }
+}
+
+// Channels, for connecting machines running on different threads.
+// Reading blocks until a value arrives or the sender hangs up.
+impl IntcodeInput for Receiver<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.recv().ok()
+    }
+}
+
+// Outputs sent after the receiving end has gone away are dropped.
+impl IntcodeOutput for Sender<i64> {
+    fn write(&mut self, value: i64) {
+        let _ = self.send(value);
+    }
+}
+
+// A channel that gives up waiting: after `timeout` with nothing received,
+// or as soon as the shutdown flag is set, reads return None.
+#[derive(Debug)]
+pub struct ChannelInput {
+    receiver: Receiver<i64>,
+    timeout: Option<Duration>,
+    shutdown: Option<Arc<AtomicBool>>,
+    timed_out: bool,
+}
+
+impl ChannelInput {
+    pub fn new(receiver: Receiver<i64>) -> ChannelInput {
+        ChannelInput {
+            receiver,
+            timeout: None,
+            shutdown: None,
+            timed_out: false,
+        }
+    }
+
+    pub fn with_timeout(receiver: Receiver<i64>, timeout: Duration) -> ChannelInput {
+        ChannelInput {
+            timeout: Some(timeout),
+            ..ChannelInput::new(receiver)
+        }
+    }
+
+    /// Stop waiting for input once `shutdown` is set.
+    pub fn shutdown_on(mut self, shutdown: Arc<AtomicBool>) -> ChannelInput {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    /// True if the last read gave up because of the timeout.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    /// The channel back, with anything that arrived after the last read still in it.
+    pub fn into_receiver(self) -> Receiver<i64> {
+        self.receiver
+    }
+
+    fn shutting_down(&self) -> bool {
+        match &self.shutdown {
+            Some(flag) => flag.load(Ordering::SeqCst),
+            None => false,
+        }
+    }
+}
+
+impl IntcodeInput for ChannelInput {
+    fn read(&mut self) -> Option<i64> {
+        self.timed_out = false;
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if self.shutting_down() {
+                return None;
+            }
+            let mut wait = SHUTDOWN_POLL;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    self.timed_out = true;
+                    return None;
+                }
+                wait = wait.min(deadline - now);
+            }
+            match self.receiver.recv_timeout(wait) {
+                Ok(value) => return Some(value),
+                Err(RecvTimeoutError::Disconnected) => return None,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+    }
+}
+
+// Reads one integer per line from stdin, optionally printing a prompt first.
+// End of input or a line that isn't an integer ends the input.
+#[derive(Debug, Default)]
+pub struct StdinInput {
+    prompt: Option<String>,
+}
+
+impl StdinInput {
+    pub fn new() -> StdinInput {
+        StdinInput { prompt: None }
+    }
+
+    pub fn with_prompt(prompt: &str) -> StdinInput {
+        StdinInput {
+            prompt: Some(prompt.to_string()),
+        }
+    }
+}
+
+impl IntcodeInput for StdinInput {
+    fn read(&mut self) -> Option<i64> {
+        if let Some(prompt) = &self.prompt {
+            println!("{}", prompt);
+        }
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => line.trim().parse().ok(),
+        }
+    }
+}
+
+// Prints each output on its own line, optionally after a label.
+#[derive(Debug, Default)]
+pub struct StdoutOutput {
+    label: Option<String>,
+}
+
+impl StdoutOutput {
+    pub fn new() -> StdoutOutput {
+        StdoutOutput { label: None }
+    }
+
+    pub fn with_label(label: &str) -> StdoutOutput {
+        StdoutOutput {
+            label: Some(label.to_string()),
+        }
+    }
+}
+
+impl IntcodeOutput for StdoutOutput {
+    fn write(&mut self, value: i64) {
+        match &self.label {
+            Some(label) => println!("{}{}", label, value),
+            None => println!("{}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn channel_input_reads_until_the_sender_hangs_up() {
+        let (sender, receiver) = mpsc::channel();
+        let mut input = ChannelInput::with_timeout(receiver, Duration::from_secs(5));
+        sender.send(1).unwrap();
+        sender.send(2).unwrap();
+        drop(sender);
+        assert_eq!(input.read(), Some(1));
+        assert_eq!(input.read(), Some(2));
+        assert_eq!(input.read(), None);
+        assert!(!input.timed_out());
+    }
+
+    #[test]
+    fn channel_input_gives_up_after_the_timeout() {
+        let (sender, receiver) = mpsc::channel::<i64>();
+        let mut input = ChannelInput::with_timeout(receiver, Duration::from_millis(30));
+        let start = Instant::now();
+        assert_eq!(input.read(), None);
+        assert!(input.timed_out());
+        assert!(start.elapsed() >= Duration::from_millis(30));
+
+        // The flag only describes the last read.
+        sender.send(3).unwrap();
+        assert_eq!(input.read(), Some(3));
+        assert!(!input.timed_out());
+    }
+
+    #[test]
+    fn channel_input_stops_waiting_on_shutdown() {
+        let (_sender, receiver) = mpsc::channel::<i64>();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let mut input = ChannelInput::new(receiver).shutdown_on(Arc::clone(&shutdown));
+        let reader = thread::spawn(move || (input.read(), input.timed_out()));
+        thread::sleep(SHUTDOWN_POLL * 3);
+        shutdown.store(true, Ordering::SeqCst);
+        assert_eq!(reader.join().unwrap(), (None, false));
+    }
+}
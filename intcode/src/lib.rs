@@ -0,0 +1,59 @@
+// Intcode
+//
+// The Intcode computer used by days 2, 5, 7, 9, 11 and 13.
+// Every day builds a `Machine` from its puzzle input and drives it from there.
+
+mod amplifier;
+mod analysis;
+mod asm;
+mod coverage;
+mod debugger;
+mod device;
+mod disasm;
+mod error;
+mod history;
+mod instruction;
+mod io;
+mod limits;
+mod machine;
+mod memory;
+mod network;
+mod permutations;
+mod profile;
+mod snapshot;
+mod symbolic;
+mod threaded;
+mod trace;
+mod transpile;
+
+pub use crate::amplifier::{AmplifierChain, AmplifierError, AmplifierTopology, BestConfiguration};
+pub use crate::analysis::{analyze, Analysis, BasicBlock, Edge, MemoryAccess, SelfModification};
+pub use crate::asm::{assemble, AsmError};
+pub use crate::coverage::{BranchCoverage, Coverage};
+pub use crate::debugger::Debugger;
+pub use crate::device::{
+    Bus, Color, Device, DeviceError, Joystick, PaintCommand, PaintRobot, ScreenCommand, Tile, TileScreen, Tilt, Turn,
+};
+pub use crate::disasm::{disassemble, format_instruction, label_name, CodeMap};
+pub use crate::error::IntcodeError;
+pub use crate::history::{Change, History};
+pub use crate::instruction::{Decoded, Instruction, Mode, OpcodeKind};
+pub use crate::io::{ChannelInput, FnInput, FnOutput, IntcodeInput, IntcodeOutput, StdinInput, StdoutOutput};
+pub use crate::limits::Limits;
+pub use crate::machine::{Machine, RunState};
+pub use crate::memory::{Memory, DEFAULT_MAX_SIZE};
+pub use crate::network::{Network, NetworkError, NetworkState, Topology, TopologyError};
+pub use crate::permutations::{Combinations, Permutations};
+pub use crate::profile::{FunctionProfile, HotLoop, Profile};
+pub use crate::snapshot::SnapshotError;
+pub use crate::symbolic::{Goal, Path, PathEnd, Solution, Symbol, SymbolicError, SymbolicMachine};
+pub use crate::threaded::Backend;
+pub use crate::trace::{read_trace, replay, write_trace, Divergence, TraceError, TraceEvent};
+pub use crate::transpile::transpile;
+
+use std::num::ParseIntError;
+
+/// Convert a comma-separated program into a vector of ints.
+pub fn parse_program(contents: &str) -> Result<Vec<i64>, ParseIntError> {
+    contents.trim().split(',').map(|x| x.trim().parse()).collect()
+}
@@ -0,0 +1,51 @@
+// Limits on how much a program may do, for running programs that can't be
+// trusted to halt or to stay within a sensible amount of memory.
+//
+// Each limit has its own error: `StepLimitExceeded` after too many
+// instructions, `MemoryLimitExceeded` for a write at or beyond the memory
+// limit, and `DeadlineExceeded` once the wall clock passes the deadline.
+// The deadline is only looked at every `DEADLINE_CHECK_INTERVAL` steps, so
+// a machine can run a little past it.
+
+use std::time::{Duration, Instant};
+
+use crate::memory::DEFAULT_MAX_SIZE;
+
+// How many steps run between looks at the clock.
+pub(crate) const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Limits {
+    /// Maximum number of instructions executed, counting from the start.
+    pub max_steps: Option<u64>,
+    /// Addresses at or above this can't be written.
+    pub max_memory: usize,
+    pub deadline: Option<Instant>,
+}
+
+impl Limits {
+    /// No step limit or deadline, and the default memory limit.
+    pub fn none() -> Limits {
+        Limits {
+            max_steps: None,
+            max_memory: DEFAULT_MAX_SIZE,
+            deadline: None,
+        }
+    }
+
+    /// Limits for a program that isn't trusted: at most `max_steps`
+    /// instructions and `max_memory` values, for no longer than `timeout` from now.
+    pub fn untrusted(max_steps: u64, max_memory: usize, timeout: Duration) -> Limits {
+        Limits {
+            max_steps: Some(max_steps),
+            max_memory,
+            deadline: Some(Instant::now() + timeout),
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits::none()
+    }
+}
@@ -0,0 +1,562 @@
+// The Intcode machine shared by every day that runs an Intcode program.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::error::IntcodeError;
+use crate::instruction::{Decoded, Instruction, Mode, OpcodeKind};
+use crate::io::{IntcodeInput, IntcodeOutput};
+use crate::limits::{Limits, DEADLINE_CHECK_INTERVAL};
+use crate::memory::Memory;
+use crate::threaded::{Backend, BlockCache};
+use crate::trace::TraceEvent;
+
+// Only instructions below this address are cached.
+const DECODE_CACHE_SIZE: usize = 1 << 16;
+// The widest instruction: an opcode and three parameters.
+const MAX_WIDTH: usize = 4;
+
+// Why the machine stopped running. Anything other than `Halted` can be
+// resumed by calling `resume` again (after pushing an input for `NeedsInput`).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RunState {
+    NeedsInput,
+    Output(i64),
+    Halted,
+}
+
+#[derive(Debug)]
+pub struct Machine {
+    pub(crate) memory: Memory,
+    pub(crate) cursor: usize,
+    pub(crate) relative_base: i64,
+    pub(crate) inputs: VecDeque<i64>,
+    pub(crate) halted: bool,
+    pub(crate) steps: u64,
+    pub(crate) step_limit: Option<u64>,
+    deadline: Option<Instant>,
+    // Events recorded while tracing is on.
+    pub(crate) trace: Option<Vec<TraceEvent>>,
+    // Instructions already decoded, by address. Entries are cleared when
+    // anything they were decoded from is written to.
+    decoded: Vec<Option<Decoded>>,
+    backend: Backend,
+    pub(crate) blocks: BlockCache,
+}
+
+// Clones share memory pages until either copy writes to them, but not the
+// decode cache: it is as big as the code, and the clone decodes again the
+// instructions it runs.
+impl Clone for Machine {
+    fn clone(&self) -> Machine {
+        Machine {
+            memory: self.memory.clone(),
+            cursor: self.cursor,
+            relative_base: self.relative_base,
+            inputs: self.inputs.clone(),
+            halted: self.halted,
+            steps: self.steps,
+            step_limit: self.step_limit,
+            deadline: self.deadline,
+            trace: self.trace.clone(),
+            decoded: Vec::new(),
+            backend: self.backend,
+            blocks: self.blocks.clone(),
+        }
+    }
+}
+
+impl Machine {
+    pub fn new(program: Vec<i64>) -> Machine {
+        Machine::with_backend(program, Backend::Interpreter)
+    }
+
+    pub fn with_backend(program: Vec<i64>, backend: Backend) -> Machine {
+        Machine {
+            memory: Memory::new(program),
+            cursor: 0,
+            relative_base: 0,
+            inputs: VecDeque::new(),
+            halted: false,
+            steps: 0,
+            step_limit: None,
+            deadline: None,
+            trace: None,
+            decoded: Vec::new(),
+            backend,
+            blocks: BlockCache::default(),
+        }
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn read(&self, address: usize) -> i64 {
+        self.memory.get(address)
+    }
+
+    /// Poke a value into memory. Panics if the address is beyond the memory limit.
+    pub fn write(&mut self, address: usize, value: i64) {
+        self.memory.set(address, value);
+        self.invalidate(address);
+    }
+
+    /// Poke a value into memory, or `MemoryLimitExceeded` if the address is beyond the memory limit.
+    pub fn try_write(&mut self, address: usize, value: i64) -> Result<(), IntcodeError> {
+        if address >= self.memory.max_size() {
+            return Err(IntcodeError::MemoryLimitExceeded {
+                cursor: self.cursor,
+                opcode_value: self.read(self.cursor),
+                address,
+                limit: self.memory.max_size(),
+            });
+        }
+        self.write(address, value);
+        Ok(())
+    }
+
+    /// Programs that write at or beyond `max_size` stop with `MemoryLimitExceeded`.
+    pub fn set_memory_limit(&mut self, max_size: usize) {
+        self.memory.set_max_size(max_size);
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Queue a value to be consumed by the next `Input` instruction.
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push_back(value);
+    }
+
+    /// Number of inputs queued and not yet consumed.
+    pub fn pending_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Stop with `StepLimitExceeded` after executing this many instructions.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Stop with `DeadlineExceeded` once the clock passes `deadline`.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Set the step limit, memory limit and deadline together.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.step_limit = limits.max_steps;
+        self.memory.set_max_size(limits.max_memory);
+        self.deadline = limits.deadline;
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_steps: self.step_limit,
+            max_memory: self.memory.max_size(),
+            deadline: self.deadline,
+        }
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Record a `TraceEvent` for every instruction executed from now on.
+    pub fn start_trace(&mut self) {
+        if self.trace.is_none() {
+            self.trace = Some(Vec::new());
+        }
+    }
+
+    /// Stop tracing and return the events recorded since `start_trace`.
+    pub fn stop_trace(&mut self) -> Vec<TraceEvent> {
+        self.trace.take().unwrap_or_default()
+    }
+
+    /// Decode the instruction at the cursor without executing it.
+    pub fn current_instruction(&self) -> Result<Instruction, IntcodeError> {
+        Instruction::new(&self.memory, self.cursor)
+    }
+
+    /// Run until the program halts, returning every output it produced.
+    /// All of the program's input must have been queued up front.
+    pub fn run(&mut self) -> Result<Vec<i64>, IntcodeError> {
+        let mut outputs = Vec::new();
+        loop {
+            match self.resume()? {
+                RunState::NeedsInput => return Err(self.input_exhausted()),
+                RunState::Output(value) => outputs.push(value),
+                RunState::Halted => return Ok(outputs),
+            }
+        }
+    }
+
+    /// Run until the next output, or return None if the program halts first.
+    pub fn run_until_output(&mut self) -> Result<Option<i64>, IntcodeError> {
+        match self.resume()? {
+            RunState::NeedsInput => Err(self.input_exhausted()),
+            RunState::Output(value) => Ok(Some(value)),
+            RunState::Halted => Ok(None),
+        }
+    }
+
+    /// Run with inputs pulled from `input` and outputs pushed to `output`.
+    /// Stops when the program halts, or returns `NeedsInput` if the program
+    /// asks for input that `input` can't supply.
+    pub fn run_with<I, O>(&mut self, mut input: I, mut output: O) -> Result<RunState, IntcodeError>
+    where
+        I: IntcodeInput,
+        O: IntcodeOutput,
+    {
+        loop {
+            match self.resume()? {
+                RunState::NeedsInput => match input.read() {
+                    Some(value) => self.push_input(value),
+                    None => return Ok(RunState::NeedsInput),
+                },
+                RunState::Output(value) => output.write(value),
+                RunState::Halted => return Ok(RunState::Halted),
+            }
+        }
+    }
+
+    // Like `run_with`, but one step at a time, calling `observe` after each
+    // instruction that executed with the machine and the instruction's
+    // address, the relative base before it ran, and the instruction.
+    pub(crate) fn run_observed<I, O, F>(&mut self, mut input: I, mut output: O, mut observe: F) -> Result<RunState, IntcodeError>
+    where
+        I: IntcodeInput,
+        O: IntcodeOutput,
+        F: FnMut(&Machine, usize, i64, &Decoded),
+    {
+        loop {
+            if self.halted {
+                return Ok(RunState::Halted);
+            }
+            let cursor = self.cursor;
+            let instruction = Decoded::new(&self.memory, cursor)?;
+            let relative_base = self.relative_base;
+            let steps = self.steps;
+            let state = self.step()?;
+            if self.steps != steps {
+                observe(self, cursor, relative_base, &instruction);
+            }
+            match state {
+                Some(RunState::NeedsInput) => match input.read() {
+                    Some(value) => self.push_input(value),
+                    None => return Ok(RunState::NeedsInput),
+                },
+                Some(RunState::Output(value)) => output.write(value),
+                Some(RunState::Halted) => return Ok(RunState::Halted),
+                None => {}
+            }
+        }
+    }
+
+    /// Step until the program blocks on input, produces an output, or halts.
+    pub fn resume(&mut self) -> Result<RunState, IntcodeError> {
+        // Tracing needs every step to go through `step`.
+        if self.backend == Backend::Threaded && self.trace.is_none() {
+            return self.resume_threaded();
+        }
+        loop {
+            if let Some(state) = self.step()? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Execute the instruction at the cursor.
+    /// Returns the new state if the instruction stopped the machine: an `Input`
+    /// with nothing queued is left unexecuted and reported as `NeedsInput`.
+    pub fn step(&mut self) -> Result<Option<RunState>, IntcodeError> {
+        if self.halted {
+            return Ok(Some(RunState::Halted));
+        }
+        let instruction = self.decode()?;
+        self.check_limits(&instruction)?;
+        let mut next_cursor = self.cursor + instruction.width();
+        let mut state = None;
+        // Operand values have to be read before the instruction can overwrite them.
+        let mut event = self.trace.as_ref().map(|_| self.trace_event(&instruction));
+
+        match instruction.opcode {
+            OpcodeKind::Add => {
+                // parameters are [noun, verb, target]
+                let result = self.read_parameter(&instruction, 0)?.checked_add(self.read_parameter(&instruction, 1)?);
+                let result = self.checked(&instruction, result)?;
+                self.write_parameter(&instruction, 2, result)?;
+            }
+            OpcodeKind::Multiply => {
+                let result = self.read_parameter(&instruction, 0)?.checked_mul(self.read_parameter(&instruction, 1)?);
+                let result = self.checked(&instruction, result)?;
+                self.write_parameter(&instruction, 2, result)?;
+            }
+            OpcodeKind::Input => match self.inputs.pop_front() {
+                Some(input) => {
+                    if let Some(event) = &mut event {
+                        event.input = Some(input);
+                    }
+                    self.write_parameter(&instruction, 0, input)?
+                }
+                None => return Ok(Some(RunState::NeedsInput)),
+            },
+            OpcodeKind::Output => {
+                state = Some(RunState::Output(self.read_parameter(&instruction, 0)?));
+            }
+            OpcodeKind::JumpIfTrue => {
+                if self.read_parameter(&instruction, 0)? != 0 {
+                    next_cursor = self.jump_target(&instruction)?;
+                }
+            }
+            OpcodeKind::JumpIfFalse => {
+                if self.read_parameter(&instruction, 0)? == 0 {
+                    next_cursor = self.jump_target(&instruction)?;
+                }
+            }
+            OpcodeKind::IsLessThan => {
+                let result = self.read_parameter(&instruction, 0)? < self.read_parameter(&instruction, 1)?;
+                self.write_parameter(&instruction, 2, result as i64)?;
+            }
+            OpcodeKind::IsEquals => {
+                let result = self.read_parameter(&instruction, 0)? == self.read_parameter(&instruction, 1)?;
+                self.write_parameter(&instruction, 2, result as i64)?;
+            }
+            OpcodeKind::AdjustRelativeBase => {
+                let base = self.relative_base.checked_add(self.read_parameter(&instruction, 0)?);
+                self.relative_base = self.checked(&instruction, base)?;
+            }
+            OpcodeKind::Exit => {
+                self.halted = true;
+                state = Some(RunState::Halted);
+                next_cursor = self.cursor;
+            }
+        }
+        if let Some(mut event) = event {
+            if instruction.opcode.writes_memory() {
+                let address = self.parameter_address(&instruction, instruction.opcode.parameter_count() - 1)?;
+                event.write = Some((address, self.memory.get(address)));
+            }
+            if let Some(RunState::Output(value)) = state {
+                event.output = Some(value);
+            }
+            if let Some(trace) = &mut self.trace {
+                trace.push(event);
+            }
+        }
+        self.steps += 1;
+        self.cursor = next_cursor;
+        Ok(state)
+    }
+
+    /// The address a parameter refers to (None in immediate mode) and the
+    /// value currently there, as the instruction would see it right now.
+    pub fn resolve_parameter(&self, instruction: &Instruction, index: usize) -> Result<(Option<usize>, i64), IntcodeError> {
+        let (mode, parameter) = (instruction.modes[index], instruction.parameters[index]);
+        match mode {
+            Mode::Immediate => Ok((None, parameter)),
+            _ => {
+                let address = self.address_of(mode, parameter, instruction.opcode_value)?;
+                Ok((Some(address), self.memory.get(address)))
+            }
+        }
+    }
+
+    // The instruction at the cursor, from the cache if it has been decoded before.
+    fn decode(&mut self) -> Result<Decoded, IntcodeError> {
+        if let Some(Some(decoded)) = self.decoded.get(self.cursor) {
+            return Ok(*decoded);
+        }
+        let decoded = Decoded::new(&self.memory, self.cursor)?;
+        if self.cursor < DECODE_CACHE_SIZE {
+            if self.cursor >= self.decoded.len() {
+                self.decoded.resize(self.cursor + 1, None);
+            }
+            self.decoded[self.cursor] = Some(decoded);
+        }
+        Ok(decoded)
+    }
+
+    // Forget any cached instruction or block that covers the address.
+    fn invalidate(&mut self, address: usize) {
+        self.blocks.invalidate(address);
+        let end = (address + 1).min(self.decoded.len());
+        let start = address.saturating_sub(MAX_WIDTH - 1).min(end);
+        for decoded in &mut self.decoded[start..end] {
+            *decoded = None;
+        }
+    }
+
+    // The event for an instruction about to execute; the results are filled in afterwards.
+    // An operand at a negative address is recorded as its raw parameter: if the
+    // instruction really reads it, executing it fails anyway.
+    fn trace_event(&self, instruction: &Decoded) -> TraceEvent {
+        let operands = (0..instruction.opcode.parameter_count())
+            .map(|index| match self.read_parameter(instruction, index) {
+                Ok(value) => value,
+                Err(_) => instruction.parameters[index],
+            })
+            .collect();
+        TraceEvent {
+            step: self.steps,
+            cursor: self.cursor,
+            opcode_value: instruction.opcode_value,
+            relative_base: self.relative_base,
+            operands,
+            write: None,
+            input: None,
+            output: None,
+        }
+    }
+
+    // Fail if running one more instruction would go over the step limit or the deadline.
+    pub(crate) fn check_limits(&self, instruction: &Decoded) -> Result<(), IntcodeError> {
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(IntcodeError::StepLimitExceeded {
+                    cursor: self.cursor,
+                    opcode_value: instruction.opcode_value,
+                    limit,
+                });
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if self.steps.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+                return Err(IntcodeError::DeadlineExceeded {
+                    cursor: self.cursor,
+                    opcode_value: instruction.opcode_value,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // The result of a checked calculation, or `Overflow` if it didn't fit.
+    pub(crate) fn checked(&self, instruction: &Decoded, result: Option<i64>) -> Result<i64, IntcodeError> {
+        result.ok_or(IntcodeError::Overflow {
+            cursor: self.cursor,
+            opcode_value: instruction.opcode_value,
+        })
+    }
+
+    fn input_exhausted(&self) -> IntcodeError {
+        IntcodeError::InputExhausted {
+            cursor: self.cursor,
+            opcode_value: self.memory.get(self.cursor),
+        }
+    }
+
+    fn parameter_address(&self, instruction: &Decoded, index: usize) -> Result<usize, IntcodeError> {
+        self.address_of(instruction.modes[index], instruction.parameters[index], instruction.opcode_value)
+    }
+
+    fn address_of(&self, mode: Mode, parameter: i64, opcode_value: i64) -> Result<usize, IntcodeError> {
+        let address = match mode {
+            Mode::Position => parameter,
+            Mode::Relative => parameter
+                .checked_add(self.relative_base)
+                .ok_or(IntcodeError::Overflow {
+                    cursor: self.cursor,
+                    opcode_value,
+                })?,
+            Mode::Immediate => {
+                return Err(IntcodeError::WriteInImmediateMode {
+                    cursor: self.cursor,
+                    opcode_value,
+                })
+            }
+        };
+        self.check_address(opcode_value, address)
+    }
+
+    fn check_address(&self, opcode_value: i64, address: i64) -> Result<usize, IntcodeError> {
+        if address < 0 {
+            return Err(IntcodeError::NegativeAddress {
+                cursor: self.cursor,
+                opcode_value,
+                address,
+            });
+        }
+        Ok(address as usize)
+    }
+
+    pub(crate) fn jump_target(&self, instruction: &Decoded) -> Result<usize, IntcodeError> {
+        let target = self.read_parameter(instruction, 1)?;
+        self.check_address(instruction.opcode_value, target)
+    }
+
+    pub(crate) fn read_parameter(&self, instruction: &Decoded, index: usize) -> Result<i64, IntcodeError> {
+        match instruction.modes[index] {
+            Mode::Immediate => Ok(instruction.parameters[index]),
+            _ => Ok(self.memory.get(self.parameter_address(instruction, index)?)),
+        }
+    }
+
+    pub(crate) fn write_parameter(&mut self, instruction: &Decoded, index: usize, value: i64) -> Result<(), IntcodeError> {
+        let address = self.parameter_address(instruction, index)?;
+        if address >= self.memory.max_size() {
+            return Err(IntcodeError::MemoryLimitExceeded {
+                cursor: self.cursor,
+                opcode_value: instruction.opcode_value,
+                address,
+                limit: self.memory.max_size(),
+            });
+        }
+        self.memory.set(address, value);
+        self.invalidate(address);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_decode_again_and_run_the_same() {
+        // Prints its input doubled, forever.
+        let program = vec![3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0, 0];
+        let mut machine = Machine::new(program);
+        machine.push_input(21);
+        assert_eq!(machine.resume(), Ok(RunState::Output(42)));
+        assert!(!machine.decoded.is_empty());
+
+        let mut clone = machine.clone();
+        assert!(clone.decoded.is_empty());
+        for (input, output) in [(5, 10), (-3, -6)].iter() {
+            machine.push_input(*input);
+            clone.push_input(*input);
+            assert_eq!(machine.resume(), Ok(RunState::Output(*output)));
+            assert_eq!(clone.resume(), Ok(RunState::Output(*output)));
+        }
+        assert_eq!(clone.steps(), machine.steps());
+    }
+
+    #[test]
+    fn clones_keep_their_own_code() {
+        let mut machine = Machine::new(vec![1101, 1, 1, 5, 104, 7, 99]);
+        let mut clone = machine.clone();
+        machine.write(5, 9);
+        assert_eq!(machine.resume(), Ok(RunState::Output(2)));
+        assert_eq!(clone.resume(), Ok(RunState::Output(2)));
+        clone.write(0, 1102);
+        assert_eq!(clone.read(0), 1102);
+        assert_eq!(machine.read(0), 1101);
+    }
+}
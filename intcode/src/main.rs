@@ -0,0 +1,160 @@
+// Intcode command line tools.
+//
+// Usage:
+//     intcode disasm <file>
+//     intcode asm <file>
+//     intcode compile <file>
+//     intcode analyze [--dot] <file>
+//     intcode debug <file>
+//     intcode trace <file> <trace file> [input...]
+//     intcode replay <file> <trace file>
+//     intcode profile <file> <folded stacks file> [input...]
+//     intcode coverage [--lcov <disassembly file>] <file> [inputs...]
+//
+// `coverage` runs the program once for each `inputs` argument, a comma-separated
+// list of the inputs for that run, and prints the coverage of all the runs together.
+
+use std::collections::VecDeque;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+const USAGE: &str = "usage: intcode disasm <file> | asm <file> | compile <file> | analyze [--dot] <file> | debug <file> | trace <file> <trace file> [input...] | replay <file> <trace file> | profile <file> <folded stacks file> [input...] | coverage [--lcov <disassembly file>] <file> [inputs...]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        println!("Application error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    match args {
+        [command, filename] if command == "disasm" => {
+            let program = read_program(filename)?;
+            print!("{}", intcode::disassemble(&program));
+        }
+        [command, filename] if command == "asm" => {
+            let source = fs::read_to_string(filename)?;
+            let program: Vec<String> = intcode::assemble(&source)?.iter().map(|x| x.to_string()).collect();
+            println!("{}", program.join(","));
+        }
+        [command, filename] if command == "compile" => {
+            print!("{}", intcode::transpile(&read_program(filename)?));
+        }
+        [command, filename] if command == "analyze" => {
+            print!("{}", intcode::analyze(&read_program(filename)?).to_text());
+        }
+        [command, flag, filename] if command == "analyze" && flag == "--dot" => {
+            print!("{}", intcode::analyze(&read_program(filename)?).to_dot());
+        }
+        [command, filename] if command == "debug" => {
+            let program = read_program(filename)?;
+            debug(intcode::Debugger::new(program))?;
+        }
+        [command, filename, trace_filename, inputs @ ..] if command == "trace" => {
+            let mut machine = intcode::Machine::new(read_program(filename)?);
+            for input in inputs {
+                machine.push_input(input.parse()?);
+            }
+            machine.start_trace();
+            let result = machine.run();
+            let events = machine.stop_trace();
+            intcode::write_trace(io::BufWriter::new(fs::File::create(trace_filename)?), &events)?;
+            println!("Output: {:?}", result?);
+            println!("{} instructions traced", events.len());
+        }
+        [command, filename, trace_filename] if command == "replay" => {
+            let program = read_program(filename)?;
+            let events = intcode::read_trace(io::BufReader::new(fs::File::open(trace_filename)?))?;
+            match intcode::replay(program, &events) {
+                Some(divergence) => return Err(divergence.to_string().into()),
+                None => println!("{} instructions match the trace", events.len()),
+            }
+        }
+        [command, filename, folded_filename, inputs @ ..] if command == "profile" => {
+            let mut machine = intcode::Machine::new(read_program(filename)?);
+            let mut inputs = inputs.iter().map(|input| input.parse::<i64>()).collect::<Result<VecDeque<_>, _>>()?;
+            let mut outputs = Vec::new();
+            let mut profile = intcode::Profile::new();
+            let state = profile.run_with(&mut machine, &mut inputs, &mut outputs);
+            fs::write(folded_filename, profile.to_folded())?;
+            print!("{}", profile.to_table());
+            println!("\nOutput: {:?}", outputs);
+            if state? == intcode::RunState::NeedsInput {
+                return Err("the program needs more input".into());
+            }
+        }
+        [command, flag, listing, filename, runs @ ..] if command == "coverage" && flag == "--lcov" => {
+            let program = read_program(filename)?;
+            let mut coverage = intcode::Coverage::new();
+            run_coverage(&mut coverage, &program, runs)?;
+            print!("{}", coverage.to_lcov(&program, listing));
+        }
+        [command, filename, runs @ ..] if command == "coverage" && !filename.starts_with("--") => {
+            let program = read_program(filename)?;
+            let mut coverage = intcode::Coverage::new();
+            let outputs = run_coverage(&mut coverage, &program, runs)?;
+            print!("{}", coverage.to_annotated(&program));
+            for (run, outputs) in runs.iter().zip(outputs) {
+                println!("; inputs {}: output {:?}", run, outputs);
+            }
+        }
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+fn debug(mut debugger: intcode::Debugger) -> Result<(), Box<dyn Error>> {
+    println!("{}", debugger.execute("list 0 1").trim_end());
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(intcode) ");
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        if line.trim() == "quit" || line.trim() == "q" {
+            break;
+        }
+        let response = debugger.execute(&line);
+        if !response.is_empty() {
+            println!("{}", response.trim_end());
+        }
+    }
+    println!();
+    Ok(())
+}
+
+// Run the program once per argument, each a comma-separated list of inputs
+// (or once with no input if there are none), adding to `coverage`. Returns
+// each run's outputs.
+fn run_coverage(coverage: &mut intcode::Coverage, program: &[i64], runs: &[String]) -> Result<Vec<Vec<i64>>, Box<dyn Error>> {
+    let no_input = [String::new()];
+    let runs = if runs.is_empty() { &no_input[..] } else { runs };
+    let mut all_outputs = Vec::new();
+    for run in runs {
+        let mut inputs = run
+            .split(',')
+            .filter(|input| !input.is_empty())
+            .map(|input| input.trim().parse::<i64>())
+            .collect::<Result<VecDeque<_>, _>>()?;
+        let mut machine = intcode::Machine::new(program.to_vec());
+        let mut outputs = Vec::new();
+        if coverage.run_with(&mut machine, &mut inputs, &mut outputs)? == intcode::RunState::NeedsInput {
+            return Err(format!("the program needs more input than {:?}", run).into());
+        }
+        all_outputs.push(outputs);
+    }
+    Ok(all_outputs)
+}
+
+fn read_program(filename: &str) -> Result<Vec<i64>, Box<dyn Error>> {
+    let contents = fs::read_to_string(filename)?;
+    Ok(intcode::parse_program(&contents)?)
+}
@@ -0,0 +1,198 @@
+// Intcode memory.
+//
+// Memory beyond the initial program starts with the value 0 and can be read
+// or written like any other memory. It is split into fixed-size pages that
+// are only allocated when first written, so a stray write to a huge address
+// doesn't try to allocate everything below it. Pages below DENSE_LIMIT are
+// found by indexing a vector; the rest live in a map.
+//
+// Pages are shared between clones and copied on write, so cloning a Machine
+// to take a snapshot or explore another branch only copies what changes.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const DENSE_LIMIT: usize = 1 << 16;
+const PAGE_SIZE: usize = 1024;
+
+// Default maximum number of addressable cells (128MB worth of i64s).
+pub const DEFAULT_MAX_SIZE: usize = 1 << 24;
+
+type Page = Arc<[i64; PAGE_SIZE]>;
+
+#[derive(Debug, Clone)]
+pub struct Memory {
+    low: Vec<Option<Page>>,
+    high: HashMap<usize, Page>,
+    len: usize,
+    max_size: usize,
+}
+
+impl Memory {
+    pub fn new(program: Vec<i64>) -> Memory {
+        let mut memory = Memory {
+            low: Vec::new(),
+            high: HashMap::new(),
+            len: program.len(),
+            max_size: DEFAULT_MAX_SIZE.max(program.len()),
+        };
+        for (index, chunk) in program.chunks(PAGE_SIZE).enumerate() {
+            memory.page_mut(index)[..chunk.len()].copy_from_slice(chunk);
+        }
+        memory
+    }
+
+    /// Addresses at or above this can't be written.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+    }
+
+    /// One past the highest address that has been written (or was part of the program).
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, address: usize) -> i64 {
+        match self.page(address / PAGE_SIZE) {
+            Some(page) => page[address % PAGE_SIZE],
+            None => 0,
+        }
+    }
+
+    /// Write a value, growing the memory if needed.
+    /// Panics if the address is beyond `max_size`.
+    pub fn set(&mut self, address: usize, value: i64) {
+        assert!(address < self.max_size, "address {} beyond memory limit", address);
+        self.page_mut(address / PAGE_SIZE)[address % PAGE_SIZE] = value;
+        self.len = self.len.max(address + 1);
+    }
+
+    // Treat everything below `len` as written, as when restoring a snapshot.
+    pub(crate) fn grow_to(&mut self, len: usize) {
+        self.len = self.len.max(len);
+    }
+
+    /// Every allocated page as (first address, contents), in address order.
+    pub(crate) fn pages(&self) -> Vec<(usize, &[i64])> {
+        let low = self
+            .low
+            .iter()
+            .enumerate()
+            .filter_map(|(index, page)| page.as_ref().map(|page| (index, page)));
+        let mut pages: Vec<(usize, &[i64])> = low
+            .chain(self.high.iter().map(|(&index, page)| (index, page)))
+            .map(|(index, page)| (index * PAGE_SIZE, &page[..]))
+            .collect();
+        pages.sort_by_key(|&(address, _)| address);
+        pages
+    }
+
+    fn page(&self, index: usize) -> Option<&Page> {
+        if index < DENSE_LIMIT / PAGE_SIZE {
+            self.low.get(index).and_then(Option::as_ref)
+        } else {
+            self.high.get(&index)
+        }
+    }
+
+    // The page, allocated if needed and copied first if a clone shares it.
+    fn page_mut(&mut self, index: usize) -> &mut [i64; PAGE_SIZE] {
+        let page = if index < DENSE_LIMIT / PAGE_SIZE {
+            if index >= self.low.len() {
+                self.low.resize(index + 1, None);
+            }
+            self.low[index].get_or_insert_with(|| Arc::new([0; PAGE_SIZE]))
+        } else {
+            self.high.entry(index).or_insert_with(|| Arc::new([0; PAGE_SIZE]))
+        };
+        Arc::make_mut(page)
+    }
+}
+
+// Two memories are equal if every address holds the same value.
+impl PartialEq for Memory {
+    fn eq(&self, other: &Memory) -> bool {
+        let end = self.len.max(other.len);
+        let pages = self.pages().into_iter().chain(other.pages());
+        self.max_size == other.max_size
+            && pages.filter(|&(start, _)| start < end).all(|(start, _)| {
+                (start..(start + PAGE_SIZE).min(end)).all(|address| self.get(address) == other.get(address))
+            })
+    }
+}
+
+impl Eq for Memory {}
+
+impl From<Vec<i64>> for Memory {
+    fn from(program: Vec<i64>) -> Memory {
+        Memory::new(program)
+    }
+}
+
+impl From<&[i64]> for Memory {
+    fn from(program: &[i64]) -> Memory {
+        Memory::new(program.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unwritten_memory_reads_as_zero_and_is_not_allocated() {
+        let mut memory = Memory::new(vec![1, 2, 3]);
+        assert_eq!((memory.get(2), memory.get(3), memory.get(usize::MAX)), (3, 0, 0));
+        assert_eq!(memory.len(), 3);
+
+        memory.set(DEFAULT_MAX_SIZE - 1, 7);
+        assert_eq!(memory.get(DEFAULT_MAX_SIZE - 1), 7);
+        assert_eq!(memory.len(), DEFAULT_MAX_SIZE);
+        assert_eq!(memory.pages().len(), 2);
+    }
+
+    #[test]
+    fn clones_share_pages_until_written() {
+        let mut memory = Memory::new(vec![5; PAGE_SIZE * 2]);
+        memory.set(DENSE_LIMIT + 1, 9);
+        let mut clone = memory.clone();
+        assert!(Arc::ptr_eq(memory.low[1].as_ref().unwrap(), clone.low[1].as_ref().unwrap()));
+
+        clone.set(PAGE_SIZE, 6);
+        clone.set(DENSE_LIMIT, 8);
+        assert!(Arc::ptr_eq(memory.low[0].as_ref().unwrap(), clone.low[0].as_ref().unwrap()));
+        assert!(!Arc::ptr_eq(memory.low[1].as_ref().unwrap(), clone.low[1].as_ref().unwrap()));
+        assert_eq!((memory.get(PAGE_SIZE), memory.get(DENSE_LIMIT)), (5, 0));
+        assert_eq!((clone.get(PAGE_SIZE), clone.get(DENSE_LIMIT)), (6, 8));
+        assert_ne!(memory, clone);
+    }
+
+    #[test]
+    fn equality_ignores_how_zeros_are_stored() {
+        let mut written = Memory::new(vec![1]);
+        written.set(2000, 0);
+        let mut grown = Memory::new(vec![1]);
+        grown.grow_to(2001);
+        assert_eq!(written, grown);
+        assert_eq!(written, Memory::new(vec![1]));
+
+        grown.set_max_size(10);
+        assert_ne!(written, grown);
+    }
+
+    #[test]
+    #[should_panic(expected = "beyond memory limit")]
+    fn writes_past_the_limit_panic() {
+        let mut memory = Memory::new(vec![0]);
+        memory.set_max_size(10);
+        memory.set(10, 1);
+    }
+}
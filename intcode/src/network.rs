@@ -0,0 +1,352 @@
+// Several machines wired together, each one's outputs feeding other
+// machines' inputs.
+//
+// The scheduler is cooperative: it runs each machine in turn until it blocks
+// on input or halts, passing outputs along as soon as they appear, and keeps
+// going round until every machine has halted or none of them can make any
+// progress. The second case is a deadlock: every machine still running is
+// waiting for input that nobody is going to send. More input can be pushed
+// in from outside and the network run again.
+//
+// `run_threaded` does the same job with every machine on its own thread,
+// connected by channels. There a deadlock can only be noticed by waiting:
+// a machine that has been blocked on input for longer than the timeout gives
+// up. When a machine fails, the others are told to stop at their next read.
+
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::IntcodeError;
+use crate::io::{ChannelInput, FnOutput};
+use crate::machine::{Machine, RunState};
+
+// How the machines' outputs are connected to inputs.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Topology {
+    /// Machine i feeds machine i + 1. The last machine's outputs leave the network.
+    Chain,
+    /// Like a chain, but the last machine feeds the first.
+    Ring,
+    /// Machine 0 feeds every other machine; their outputs leave the network.
+    Broadcast,
+    /// Arbitrary (from, to) links. A machine with no links out feeds nobody.
+    Graph(Vec<(usize, usize)>),
+}
+
+impl Topology {
+    // For each machine, the machines its outputs are sent to.
+    fn links(&self, count: usize) -> Result<Vec<Vec<usize>>, TopologyError> {
+        let mut links = vec![Vec::new(); count];
+        match self {
+            Topology::Chain => {
+                for from in 1..count {
+                    links[from - 1].push(from);
+                }
+            }
+            Topology::Ring => {
+                for (from, to) in links.iter_mut().enumerate() {
+                    to.push((from + 1) % count);
+                }
+            }
+            Topology::Broadcast => {
+                if count > 0 {
+                    links[0].extend(1..count);
+                }
+            }
+            Topology::Graph(edges) => {
+                for &(from, to) in edges {
+                    if from >= count || to >= count {
+                        return Err(TopologyError { from, to, machines: count });
+                    }
+                    links[from].push(to);
+                }
+            }
+        }
+        Ok(links)
+    }
+}
+
+// A `Topology::Graph` link names a machine that isn't in the network.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TopologyError {
+    pub from: usize,
+    pub to: usize,
+    pub machines: usize,
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "link {} -> {} is outside the network of {} machines", self.from, self.to, self.machines)
+    }
+}
+
+impl Error for TopologyError {}
+
+// Why `Network::run` stopped.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum NetworkState {
+    Halted,
+    /// These machines are all waiting for input and none can be given any.
+    Deadlocked { waiting: Vec<usize> },
+}
+
+// A machine in the network failed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct NetworkError {
+    pub machine: usize,
+    pub error: IntcodeError,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "machine {}: {}", self.machine, self.error)
+    }
+}
+
+impl Error for NetworkError {}
+
+#[derive(Debug, Clone)]
+pub struct Network {
+    machines: Vec<Machine>,
+    links: Vec<Vec<usize>>,
+    // Everything each machine has output, whoever it was sent to.
+    outputs: Vec<Vec<i64>>,
+}
+
+impl Network {
+    /// Fails if a `Topology::Graph` link names a machine that isn't there.
+    pub fn new(machines: Vec<Machine>, topology: Topology) -> Result<Network, TopologyError> {
+        let links = topology.links(machines.len())?;
+        Ok(Network {
+            outputs: vec![Vec::new(); machines.len()],
+            machines,
+            links,
+        })
+    }
+
+    /// One machine per phase setting, each running a copy of `program` with
+    /// its phase setting queued as its first input.
+    pub fn with_phases(program: &[i64], phases: &[i64], topology: Topology) -> Result<Network, TopologyError> {
+        let machines = phases
+            .iter()
+            .map(|&phase| {
+                let mut machine = Machine::new(program.to_vec());
+                machine.push_input(phase);
+                machine
+            })
+            .collect();
+        Network::new(machines, topology)
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    pub fn machine(&self, index: usize) -> &Machine {
+        &self.machines[index]
+    }
+
+    pub fn machine_mut(&mut self, index: usize) -> &mut Machine {
+        &mut self.machines[index]
+    }
+
+    /// Give a machine an input from outside the network.
+    pub fn push_input(&mut self, machine: usize, value: i64) {
+        self.machines[machine].push_input(value);
+    }
+
+    /// Every value a machine has output so far.
+    pub fn outputs(&self, machine: usize) -> &[i64] {
+        &self.outputs[machine]
+    }
+
+    /// Run every machine until they have all halted or the network deadlocks.
+    pub fn run(&mut self) -> Result<NetworkState, NetworkError> {
+        loop {
+            let mut progress = false;
+            for index in 0..self.machines.len() {
+                progress |= self.run_machine(index)?;
+            }
+            if self.machines.iter().all(Machine::is_halted) {
+                return Ok(NetworkState::Halted);
+            }
+            if !progress {
+                let waiting = (0..self.machines.len()).filter(|&i| !self.machines[i].is_halted()).collect();
+                return Ok(NetworkState::Deadlocked { waiting });
+            }
+        }
+    }
+
+    // Run one machine until it blocks or halts. Returns true if it executed anything.
+    fn run_machine(&mut self, index: usize) -> Result<bool, NetworkError> {
+        let steps = self.machines[index].steps();
+        loop {
+            let state = self.machines[index]
+                .resume()
+                .map_err(|error| NetworkError { machine: index, error })?;
+            match state {
+                RunState::Output(value) => {
+                    self.outputs[index].push(value);
+                    for &to in &self.links[index] {
+                        self.machines[to].push_input(value);
+                    }
+                }
+                RunState::NeedsInput | RunState::Halted => break,
+            }
+        }
+        Ok(self.machines[index].steps() != steps)
+    }
+
+    /// Run every machine on its own thread until they have all halted, or
+    /// every one still running has waited `timeout` for input without getting
+    /// any. Inputs already pushed are delivered first; values a machine never
+    /// got round to reading are left queued as its inputs.
+    pub fn run_threaded(&mut self, timeout: Duration) -> Result<NetworkState, NetworkError> {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..self.machines.len()).map(|_| mpsc::channel()).unzip();
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let mut handles = Vec::new();
+        for (index, (mut machine, receiver)) in mem::take(&mut self.machines).into_iter().zip(receivers).enumerate() {
+            let targets: Vec<mpsc::Sender<i64>> = self.links[index].iter().map(|&to| senders[to].clone()).collect();
+            let mut input = ChannelInput::with_timeout(receiver, timeout).shutdown_on(Arc::clone(&shutdown));
+            let shutdown = Arc::clone(&shutdown);
+            handles.push(thread::spawn(move || {
+                let mut outputs = Vec::new();
+                let result = machine.run_with(
+                    &mut input,
+                    FnOutput(|value| {
+                        outputs.push(value);
+                        for target in &targets {
+                            let _ = target.send(value);
+                        }
+                    }),
+                );
+                if result.is_err() {
+                    shutdown.store(true, Ordering::SeqCst);
+                }
+                // Dropping `targets` here lets machines downstream see that no more input is coming.
+                // The receiver goes back so values that arrive after this machine stopped aren't lost.
+                (machine, outputs, result, input.into_receiver())
+            }));
+        }
+        // Only the machines should hold senders, so a machine whose inputs have all halted stops waiting.
+        drop(senders);
+
+        let mut error = None;
+        let mut receivers = Vec::new();
+        for (index, handle) in handles.into_iter().enumerate() {
+            let (machine, outputs, result, receiver) = handle.join().expect("machine thread panicked");
+            self.machines.push(machine);
+            self.outputs[index].extend(outputs);
+            receivers.push(receiver);
+            if let (Err(e), None) = (result, error) {
+                error = Some(NetworkError { machine: index, error: e });
+            }
+        }
+        // Every thread has finished, so nothing more can be sent: queue what was
+        // still in flight, e.g. to a machine that had already timed out.
+        for (machine, receiver) in self.machines.iter_mut().zip(receivers) {
+            for value in receiver.try_iter() {
+                machine.push_input(value);
+            }
+        }
+        if let Some(error) = error {
+            return Err(error);
+        }
+        if self.machines.iter().all(Machine::is_halted) {
+            return Ok(NetworkState::Halted);
+        }
+        let waiting = (0..self.machines.len()).filter(|&i| !self.machines[i].is_halted()).collect();
+        Ok(NetworkState::Deadlocked { waiting })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Outputs each input plus one, until it reads a 0.
+    fn incrementer() -> Machine {
+        Machine::new(vec![3, 15, 1006, 15, 14, 101, 1, 15, 15, 4, 15, 1105, 1, 0, 99, 0])
+    }
+
+    #[test]
+    fn graph_links_outside_the_network_are_rejected() {
+        let machines = vec![incrementer(), incrementer()];
+        let error = Network::new(machines, Topology::Graph(vec![(0, 1), (1, 2)])).unwrap_err();
+        assert_eq!(error, TopologyError { from: 1, to: 2, machines: 2 });
+        assert!(Network::new(Vec::new(), Topology::Graph(vec![(0, 0)])).is_err());
+        assert!(Network::new(vec![incrementer()], Topology::Graph(vec![(0, 0)])).is_ok());
+    }
+
+    // Day 7's feedback loop example: phases 9,8,7,6,5 give 139629729.
+    const FEEDBACK: [i64; 29] = [
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99, 0, 0, 5,
+    ];
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn threaded_rings_agree_with_cooperative_ones() {
+        let mut cooperative = Network::with_phases(&FEEDBACK, &[9, 8, 7, 6, 5], Topology::Ring).unwrap();
+        let mut threaded = cooperative.clone();
+        cooperative.push_input(0, 0);
+        threaded.push_input(0, 0);
+        assert_eq!(cooperative.run(), Ok(NetworkState::Halted));
+        assert_eq!(threaded.run_threaded(TIMEOUT), Ok(NetworkState::Halted));
+        assert_eq!(threaded.outputs(4).last(), Some(&139629729));
+        for machine in 0..5 {
+            assert_eq!(threaded.outputs(machine), cooperative.outputs(machine));
+            assert!(threaded.machine(machine).is_halted());
+        }
+    }
+
+    #[test]
+    fn threaded_networks_time_out_when_deadlocked() {
+        // Each waits for the other's first output.
+        let machines = vec![incrementer(), incrementer()];
+        let mut network = Network::new(machines, Topology::Ring).unwrap();
+        let state = network.run_threaded(Duration::from_millis(50));
+        assert_eq!(state, Ok(NetworkState::Deadlocked { waiting: vec![0, 1] }));
+
+        // Inputs pushed afterwards are delivered on the next run; a 0 stops each machine.
+        network.push_input(0, 0);
+        network.push_input(1, 0);
+        assert_eq!(network.run_threaded(TIMEOUT), Ok(NetworkState::Halted));
+    }
+
+    #[test]
+    fn values_sent_after_a_machine_times_out_are_kept() {
+        // Counts down from 1000000 before sending 5 and 0, long after machine 1 has given up.
+        let slow = Machine::new(vec![1101, 0, 1_000_000, 17, 1001, 17, -1, 17, 1005, 17, 4, 104, 5, 104, 0, 99, 0, 0]);
+        let machines = vec![slow, incrementer()];
+        let mut network = Network::new(machines, Topology::Graph(vec![(0, 1)])).unwrap();
+        assert!(network.run_threaded(Duration::from_millis(1)).is_ok());
+        assert!(network.machine(0).is_halted());
+
+        // Whether machine 1 read them in time or not, it gets both.
+        assert_eq!(network.run(), Ok(NetworkState::Halted));
+        assert_eq!(network.outputs(1), &[6]);
+    }
+
+    #[test]
+    fn a_failing_machine_stops_the_others() {
+        // Machine 1 fails on its first instruction; machine 0 would wait for it forever.
+        let machines = vec![incrementer(), Machine::new(vec![98])];
+        let mut network = Network::new(machines, Topology::Ring).unwrap();
+        let error = network.run_threaded(Duration::from_secs(60)).unwrap_err();
+        assert_eq!(error.machine, 1);
+        assert!(matches!(error.error, IntcodeError::UnknownOpcode { .. }));
+        assert_eq!(network.len(), 2);
+        assert!(!network.machine(0).is_halted());
+    }
+}
@@ -0,0 +1,126 @@
+// Iterators over orderings and selections of a set of values, for brute-force
+// searches like day 7's phase settings.
+
+// Every ordering of the items, in lexicographic order of their positions:
+// the first permutation is the items as given, the last is them reversed.
+#[derive(Debug, Clone)]
+pub struct Permutations<T> {
+    items: Vec<T>,
+    indices: Vec<usize>,
+    done: bool,
+}
+
+impl<T: Clone> Permutations<T> {
+    pub fn new(items: &[T]) -> Permutations<T> {
+        Permutations {
+            items: items.to_vec(),
+            indices: (0..items.len()).collect(),
+            done: false,
+        }
+    }
+}
+
+impl<T: Clone> Iterator for Permutations<T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        if self.done {
+            return None;
+        }
+        let permutation = self.indices.iter().map(|&i| self.items[i].clone()).collect();
+
+        // Step the indices on to the next permutation in lexicographic order.
+        let n = self.indices.len();
+        match (1..n).rev().find(|&i| self.indices[i - 1] < self.indices[i]) {
+            Some(i) => {
+                let pivot = i - 1;
+                let swap = (i..n).rev().find(|&j| self.indices[j] > self.indices[pivot]).unwrap();
+                self.indices.swap(pivot, swap);
+                self.indices[i..].reverse();
+            }
+            None => self.done = true,
+        }
+        Some(permutation)
+    }
+}
+
+// Every way of choosing `k` of the items, keeping them in their original order.
+#[derive(Debug, Clone)]
+pub struct Combinations<T> {
+    items: Vec<T>,
+    indices: Vec<usize>,
+    done: bool,
+}
+
+impl<T: Clone> Combinations<T> {
+    pub fn new(items: &[T], k: usize) -> Combinations<T> {
+        Combinations {
+            items: items.to_vec(),
+            indices: (0..k).collect(),
+            done: k > items.len(),
+        }
+    }
+}
+
+impl<T: Clone> Iterator for Combinations<T> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        if self.done {
+            return None;
+        }
+        let combination = self.indices.iter().map(|&i| self.items[i].clone()).collect();
+
+        // Advance the rightmost index that can still move right, and reset the ones after it.
+        let (n, k) = (self.items.len(), self.indices.len());
+        match (0..k).rev().find(|&i| self.indices[i] < n - k + i) {
+            Some(i) => {
+                self.indices[i] += 1;
+                for j in i + 1..k {
+                    self.indices[j] = self.indices[j - 1] + 1;
+                }
+            }
+            None => self.done = true,
+        }
+        Some(combination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn permutations_are_every_ordering_once_in_order() {
+        let permutations: Vec<Vec<i64>> = Permutations::new(&[5, 6, 7, 8, 9]).collect();
+        assert_eq!(permutations.len(), 120);
+        assert_eq!(permutations.iter().collect::<HashSet<_>>().len(), 120);
+        assert!(permutations.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(permutations.first(), Some(&vec![5, 6, 7, 8, 9]));
+        assert_eq!(permutations.last(), Some(&vec![9, 8, 7, 6, 5]));
+    }
+
+    #[test]
+    fn permutations_follow_positions_not_values() {
+        let permutations: Vec<Vec<char>> = Permutations::new(&['c', 'a', 'b']).collect();
+        let expected = ["cab", "cba", "acb", "abc", "bca", "bac"];
+        let expected: Vec<Vec<char>> = expected.iter().map(|p| p.chars().collect()).collect();
+        assert_eq!(permutations, expected);
+    }
+
+    #[test]
+    fn permutations_of_nothing_and_of_one_item() {
+        assert_eq!(Permutations::<i64>::new(&[]).collect::<Vec<_>>(), vec![Vec::<i64>::new()]);
+        assert_eq!(Permutations::new(&[7]).collect::<Vec<_>>(), vec![vec![7]]);
+    }
+
+    #[test]
+    fn combinations_choose_k_in_order() {
+        let combinations: Vec<Vec<i64>> = Combinations::new(&[1, 2, 3, 4], 2).collect();
+        assert_eq!(combinations, vec![vec![1, 2], vec![1, 3], vec![1, 4], vec![2, 3], vec![2, 4], vec![3, 4]]);
+        assert_eq!(Combinations::new(&[1, 2, 3], 0).collect::<Vec<_>>(), vec![Vec::<i64>::new()]);
+        assert_eq!(Combinations::new(&[1, 2, 3], 3).count(), 1);
+        assert_eq!(Combinations::new(&[1, 2, 3], 4).count(), 0);
+    }
+}
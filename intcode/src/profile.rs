@@ -0,0 +1,393 @@
+// An instruction-level profiler.
+//
+// `Profile::run_with` runs a machine one step at a time, like
+// `Machine::run_with`, and counts how often each address and each kind of
+// instruction executes, how many times each backward jump is taken (a loop),
+// how far memory grows and where the relative base goes.
+//
+// Intcode has no call instruction, so calls are recognised by the usual
+// calling convention: the return address (the one just after the jump) is
+// written to memory, then an immediate jump goes to the function. A jump to
+// a target held in memory that matches a return address on the call stack
+// is a return. Steps are counted against the stack of functions they ran in,
+// which `to_folded` writes out in the folded-stack format flamegraph tools
+// read: one line per stack, frames separated by `;`, then the step count.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::disasm::label_name;
+use crate::error::IntcodeError;
+use crate::instruction::{Decoded, Mode, OpcodeKind};
+use crate::io::{IntcodeInput, IntcodeOutput};
+use crate::machine::{Machine, RunState};
+
+// How many of the busiest addresses the table lists.
+const TABLE_ROWS: usize = 20;
+
+// A loop: a backward jump, and the code from its target up to the jump.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct HotLoop {
+    pub start: usize,
+    /// One past the jump instruction.
+    pub end: usize,
+    /// How many times the jump was taken.
+    pub iterations: u64,
+    /// Steps executed between `start` and `end`.
+    pub steps: u64,
+}
+
+// Steps spent in one function: in its own code, and including everything it called.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct FunctionProfile {
+    pub entry: usize,
+    pub calls: u64,
+    pub self_steps: u64,
+    pub total_steps: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    steps: u64,
+    by_address: Vec<u64>,
+    // The kind of instruction last executed at each address.
+    opcode_at: Vec<Option<OpcodeKind>>,
+    by_opcode: HashMap<OpcodeKind, u64>,
+    memory_high_water: usize,
+    relative_base_range: Option<(i64, i64)>,
+    // Taken backward jumps, by (target, address of the jump).
+    back_jumps: HashMap<(usize, usize), u64>,
+    calls: HashMap<usize, u64>,
+    deepest: usize,
+    // Steps by call stack; each stack is the entry points of the functions on it.
+    stacks: HashMap<Vec<usize>, u64>,
+    frames: Vec<usize>,
+    returns: Vec<usize>,
+    // The value most recently written to memory, which might be a return address.
+    last_write: Option<i64>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    /// Run with inputs pulled from `input` and outputs pushed to `output`,
+    /// profiling every step. Stops in the same places as `Machine::run_with`.
+    pub fn run_with<I, O>(&mut self, machine: &mut Machine, input: I, output: O) -> Result<RunState, IntcodeError>
+    where
+        I: IntcodeInput,
+        O: IntcodeOutput,
+    {
+        machine.run_observed(input, output, |machine, cursor, relative_base, instruction| {
+            self.record(machine, cursor, relative_base, instruction)
+        })
+    }
+
+    // Account for one executed instruction. The machine has already run it.
+    fn record(&mut self, machine: &Machine, cursor: usize, relative_base: i64, instruction: &Decoded) {
+        self.steps += 1;
+        if self.by_address.len() <= cursor {
+            self.by_address.resize(cursor + 1, 0);
+            self.opcode_at.resize(cursor + 1, None);
+        }
+        self.by_address[cursor] += 1;
+        self.opcode_at[cursor] = Some(instruction.opcode);
+        *self.by_opcode.entry(instruction.opcode).or_insert(0) += 1;
+        self.memory_high_water = self.memory_high_water.max(machine.memory().len());
+        let (low, high) = self.relative_base_range.unwrap_or((relative_base, relative_base));
+        let base = machine.relative_base();
+        self.relative_base_range = Some((low.min(relative_base).min(base), high.max(relative_base).max(base)));
+
+        // The step belongs to the function it ran in, before any call or return it makes.
+        match self.stacks.get_mut(self.frames.as_slice()) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(self.frames.clone(), 1);
+            }
+        }
+
+        if instruction.opcode.writes_memory() {
+            let parameter = instruction.parameters[instruction.opcode.parameter_count() - 1];
+            let address = match instruction.modes[instruction.opcode.parameter_count() - 1] {
+                Mode::Relative => relative_base + parameter,
+                _ => parameter,
+            };
+            self.last_write = Some(machine.read(address as usize));
+        }
+
+        if !matches!(instruction.opcode, OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse) {
+            return;
+        }
+        let fall_through = cursor + instruction.width();
+        let write = self.last_write.take();
+        if machine.cursor() == fall_through {
+            return;
+        }
+        let target = machine.cursor();
+        if instruction.modes[1] == Mode::Immediate && write == Some(fall_through as i64) {
+            self.frames.push(target);
+            self.returns.push(fall_through);
+            self.deepest = self.deepest.max(self.frames.len());
+            *self.calls.entry(target).or_insert(0) += 1;
+        } else if let Some(depth) = self.returns.iter().rposition(|&address| address == target) {
+            self.frames.truncate(depth);
+            self.returns.truncate(depth);
+        } else if target <= cursor {
+            // Only a backward jump that isn't a call or a return closes a loop.
+            *self.back_jumps.entry((target, cursor)).or_insert(0) += 1;
+        }
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// How many times the instruction at an address was executed.
+    pub fn count_at(&self, address: usize) -> u64 {
+        self.by_address.get(address).copied().unwrap_or(0)
+    }
+
+    /// Every address that was executed, busiest first.
+    pub fn addresses(&self) -> Vec<(usize, u64)> {
+        let mut addresses: Vec<(usize, u64)> =
+            self.by_address.iter().enumerate().filter(|(_, &count)| count > 0).map(|(a, &c)| (a, c)).collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses
+    }
+
+    /// Steps by kind of instruction, busiest first.
+    pub fn opcodes(&self) -> Vec<(OpcodeKind, u64)> {
+        let mut opcodes: Vec<(OpcodeKind, u64)> = self.by_opcode.iter().map(|(&kind, &count)| (kind, count)).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.value().cmp(&b.0.value())));
+        opcodes
+    }
+
+    /// One past the highest address the program used.
+    pub fn memory_high_water(&self) -> usize {
+        self.memory_high_water
+    }
+
+    /// The lowest and highest relative base, or None if nothing ran.
+    pub fn relative_base_range(&self) -> Option<(i64, i64)> {
+        self.relative_base_range
+    }
+
+    /// Loops that were taken at least once, most steps first.
+    pub fn hot_loops(&self) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self
+            .back_jumps
+            .iter()
+            .map(|(&(start, jump), &iterations)| {
+                let end = jump + 3;
+                HotLoop {
+                    start,
+                    end,
+                    iterations,
+                    steps: (start..end).map(|address| self.count_at(address)).sum(),
+                }
+            })
+            .collect();
+        loops.sort_by(|a, b| b.steps.cmp(&a.steps).then(a.start.cmp(&b.start)));
+        loops
+    }
+
+    /// The most functions that were on the call stack at once.
+    pub fn deepest_call_stack(&self) -> usize {
+        self.deepest
+    }
+
+    /// Every function that was called, most total steps first.
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions: HashMap<usize, FunctionProfile> = self
+            .calls
+            .iter()
+            .map(|(&entry, &calls)| {
+                let function = FunctionProfile {
+                    entry,
+                    calls,
+                    self_steps: 0,
+                    total_steps: 0,
+                };
+                (entry, function)
+            })
+            .collect();
+        for (stack, &count) in &self.stacks {
+            if let Some(function) = stack.last().and_then(|entry| functions.get_mut(entry)) {
+                function.self_steps += count;
+            }
+            // A recursive function counts each step once, however deep it is.
+            let mut seen = Vec::new();
+            for entry in stack {
+                if !seen.contains(entry) {
+                    seen.push(*entry);
+                    if let Some(function) = functions.get_mut(entry) {
+                        function.total_steps += count;
+                    }
+                }
+            }
+        }
+        let mut functions: Vec<FunctionProfile> = functions.into_values().collect();
+        functions.sort_by(|a, b| b.total_steps.cmp(&a.total_steps).then(a.entry.cmp(&b.entry)));
+        functions
+    }
+
+    /// A report of the busiest instructions, loops and functions.
+    pub fn to_table(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "total steps: {}", self.steps);
+        let _ = writeln!(text, "memory high-water mark: {} values", self.memory_high_water);
+        if let Some((low, high)) = self.relative_base_range {
+            let _ = writeln!(text, "relative base range: {} to {}", low, high);
+        }
+
+        let _ = writeln!(text, "\nby opcode:");
+        for (kind, count) in self.opcodes() {
+            let _ = writeln!(text, "  {:<5} {:>12} {:>6.2}%", kind.mnemonic(), count, self.percent(count));
+        }
+
+        let _ = writeln!(text, "\nbusiest addresses:");
+        for (address, count) in self.addresses().into_iter().take(TABLE_ROWS) {
+            let mnemonic = self.opcode_at[address].map_or("", OpcodeKind::mnemonic);
+            let _ = writeln!(text, "  {:04} {:<5} {:>12} {:>6.2}%", address, mnemonic, count, self.percent(count));
+        }
+
+        let _ = writeln!(text, "\nhot loops:");
+        let loops = self.hot_loops();
+        if loops.is_empty() {
+            let _ = writeln!(text, "  none");
+        }
+        for hot in loops.iter().take(TABLE_ROWS) {
+            let _ = writeln!(
+                text,
+                "  {:04}-{:04} {:>10} iterations {:>12} steps {:>6.2}%",
+                hot.start,
+                hot.end - 1,
+                hot.iterations,
+                hot.steps,
+                self.percent(hot.steps)
+            );
+        }
+
+        let _ = writeln!(text, "\nfunctions (deepest call stack: {}):", self.deepest);
+        let functions = self.functions();
+        if functions.is_empty() {
+            let _ = writeln!(text, "  none");
+        }
+        for function in functions.iter().take(TABLE_ROWS) {
+            let _ = writeln!(
+                text,
+                "  {} {:>10} calls {:>12} self {:>12} total {:>6.2}%",
+                label_name(function.entry),
+                function.calls,
+                function.self_steps,
+                function.total_steps,
+                self.percent(function.total_steps)
+            );
+        }
+        text
+    }
+
+    /// Steps by call stack in folded-stack format, the outermost frame being `main`.
+    pub fn to_folded(&self) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| {
+                let mut line = String::from("main");
+                for &entry in stack {
+                    line.push(';');
+                    line.push_str(&label_name(entry));
+                }
+                format!("{} {}", line, count)
+            })
+            .collect();
+        lines.sort();
+        let mut folded = lines.join("\n");
+        folded.push('\n');
+        folded
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        if self.steps == 0 {
+            0.0
+        } else {
+            100.0 * count as f64 / self.steps as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // Calls a function at 20 twice, which counts [101] up three times in a
+    // loop at 24-34 and returns through [100]; then outputs [101].
+    #[rustfmt::skip]
+    const PROGRAM: [i64; 38] = [
+        1101, 0, 7, 100, 1105, 1, 20,
+        1101, 0, 14, 100, 1105, 1, 20,
+        4, 101, 99, 0, 0, 0,
+        1101, 3, 0, 102,
+        1001, 102, -1, 102,
+        1001, 101, 1, 101,
+        1005, 102, 24,
+        106, 0, 100,
+    ];
+
+    fn profile() -> Profile {
+        let mut machine = Machine::new(PROGRAM.to_vec());
+        let mut profile = Profile::new();
+        let mut outputs = Vec::new();
+        assert_eq!(profile.run_with(&mut machine, VecDeque::new(), &mut outputs), Ok(RunState::Halted));
+        assert_eq!(outputs, vec![6]);
+        profile
+    }
+
+    #[test]
+    fn steps_are_counted_by_address_and_opcode() {
+        let profile = profile();
+        assert_eq!(profile.steps(), 28);
+        assert_eq!(profile.count_at(24), 6);
+        assert_eq!(profile.count_at(0), 1);
+        assert_eq!(profile.count_at(17), 0);
+        assert_eq!(profile.addresses()[..3], [(24, 6), (28, 6), (32, 6)]);
+        let opcodes = vec![
+            (OpcodeKind::Add, 16),
+            (OpcodeKind::JumpIfTrue, 8),
+            (OpcodeKind::JumpIfFalse, 2),
+            (OpcodeKind::Output, 1),
+            (OpcodeKind::Exit, 1),
+        ];
+        assert_eq!(profile.opcodes(), opcodes);
+        assert_eq!(profile.memory_high_water(), 103);
+        assert_eq!(profile.relative_base_range(), Some((0, 0)));
+    }
+
+    #[test]
+    fn backward_jumps_are_loops_but_calls_and_returns_are_not() {
+        let hot = HotLoop {
+            start: 24,
+            end: 35,
+            iterations: 4,
+            steps: 18,
+        };
+        assert_eq!(profile().hot_loops(), vec![hot]);
+    }
+
+    #[test]
+    fn steps_are_charged_to_the_function_they_ran_in() {
+        let profile = profile();
+        let function = FunctionProfile {
+            entry: 20,
+            calls: 2,
+            self_steps: 22,
+            total_steps: 22,
+        };
+        assert_eq!(profile.functions(), vec![function]);
+        assert_eq!(profile.deepest_call_stack(), 1);
+        assert_eq!(profile.to_folded(), "main 6\nmain;L0020 22\n");
+        assert!(profile.to_table().contains("  L0020          2 calls           22 self           22 total  78.57%"));
+    }
+}
@@ -0,0 +1,196 @@
+// Saving a machine to a file and loading it again.
+//
+// A snapshot is a small text file with one field per line:
+//
+//     intcode snapshot
+//     cursor 25
+//     relative_base 1000
+//     halted false
+//     steps 1342
+//     step_limit 100000
+//     memory_limit 16777216
+//     memory_len 2000
+//     inputs 1,0
+//     memory 0 1102,34463338,34463338,63,...
+//     memory 1024 0,0,5,...
+//
+// `step_limit` is left out when there isn't one. Each `memory` line holds a
+// run of values starting at the given address; anything not listed is 0.
+// A trace being recorded is not part of the snapshot, and neither is the
+// backend (loaded machines use the interpreter) or the deadline, which is a
+// moment in time that means nothing once the snapshot is loaded again.
+//
+// For snapshots that stay in memory, just clone the Machine: memory pages
+// are shared until one of the copies writes to them.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::machine::Machine;
+use crate::memory::Memory;
+
+const HEADER: &str = "intcode snapshot";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::Parse { line, message } => write!(f, "snapshot line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> SnapshotError {
+        SnapshotError::Io(e)
+    }
+}
+
+impl Machine {
+    /// Write the whole state of the machine in the snapshot format.
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        writeln!(writer, "cursor {}", self.cursor)?;
+        writeln!(writer, "relative_base {}", self.relative_base)?;
+        writeln!(writer, "halted {}", self.halted)?;
+        writeln!(writer, "steps {}", self.steps)?;
+        if let Some(limit) = self.step_limit {
+            writeln!(writer, "step_limit {}", limit)?;
+        }
+        writeln!(writer, "memory_limit {}", self.memory.max_size())?;
+        writeln!(writer, "memory_len {}", self.memory.len())?;
+        writeln!(writer, "inputs {}", join(self.inputs.iter()))?;
+        for (start, values) in self.memory.pages() {
+            // Trailing zeros don't need saving, and neither do pages of nothing but zeros.
+            let used = values.iter().rposition(|&value| value != 0).map_or(0, |last| last + 1);
+            if used > 0 {
+                writeln!(writer, "memory {} {}", start, join(values[..used].iter()))?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// Read a machine back from a snapshot written by `save`.
+    pub fn load<R: BufRead>(reader: R) -> Result<Machine, SnapshotError> {
+        let mut machine = Machine::new(Vec::new());
+        let mut memory = Memory::new(Vec::new());
+        let mut memory_len = 0;
+        let mut saw_header = false;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            let error = |message: String| SnapshotError::Parse {
+                line: index + 1,
+                message,
+            };
+            if line.is_empty() {
+                continue;
+            }
+            if !saw_header {
+                if line != HEADER {
+                    return Err(error("not an intcode snapshot".to_string()));
+                }
+                saw_header = true;
+                continue;
+            }
+            let (key, value) = match line.find(' ') {
+                Some(split) => (&line[..split], line[split + 1..].trim()),
+                None => (line, ""),
+            };
+            match key {
+                "cursor" => machine.cursor = parse(value).map_err(error)?,
+                "relative_base" => machine.relative_base = parse(value).map_err(error)?,
+                "halted" => machine.halted = parse(value).map_err(error)?,
+                "steps" => machine.steps = parse(value).map_err(error)?,
+                "step_limit" => machine.step_limit = Some(parse(value).map_err(error)?),
+                "memory_limit" => memory.set_max_size(parse(value).map_err(error)?),
+                "memory_len" => memory_len = parse(value).map_err(error)?,
+                "inputs" => machine.inputs = parse_list(value).map_err(error)?.into_iter().collect::<VecDeque<_>>(),
+                "memory" => {
+                    let (start, values) = match value.find(' ') {
+                        Some(split) => (&value[..split], &value[split + 1..]),
+                        None => return Err(error("memory needs an address and values".to_string())),
+                    };
+                    let start: usize = parse(start).map_err(error)?;
+                    let values = parse_list(values).map_err(error)?;
+                    let fits = match start.checked_add(values.len()) {
+                        Some(end) => end <= memory.max_size(),
+                        None => false,
+                    };
+                    if !fits {
+                        return Err(error(format!("memory at {} is beyond the memory limit", start)));
+                    }
+                    for (offset, value) in values.into_iter().enumerate() {
+                        memory.set(start + offset, value);
+                    }
+                }
+                _ => return Err(error(format!("unknown field {}", key))),
+            }
+        }
+        if !saw_header {
+            return Err(SnapshotError::Parse {
+                line: 0,
+                message: "empty snapshot".to_string(),
+            });
+        }
+        memory.grow_to(memory_len);
+        machine.memory = memory;
+        Ok(machine)
+    }
+}
+
+fn join<'a>(values: impl Iterator<Item = &'a i64>) -> String {
+    values.map(|value| value.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("bad value {:?}", text))
+}
+
+fn parse_list(text: &str) -> Result<Vec<i64>, String> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    text.split(',').map(|value| parse(value.trim())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_load_back_to_the_same_machine() {
+        let mut machine = Machine::new(vec![3, 9, 4, 9, 109, 5, 99, 0, 0, 0]);
+        machine.push_input(7);
+        machine.push_input(8);
+        machine.step().unwrap();
+        let mut snapshot = Vec::new();
+        machine.save(&mut snapshot).unwrap();
+
+        let loaded = Machine::load(&snapshot[..]).unwrap();
+        assert_eq!(loaded.cursor(), 2);
+        assert_eq!(loaded.read(9), 7);
+        assert_eq!(loaded.pending_inputs(), 1);
+        assert_eq!(loaded.memory().len(), machine.memory().len());
+    }
+
+    #[test]
+    fn memory_past_the_address_space_is_rejected() {
+        let snapshot = format!("{}\nmemory {} 1,2\n", HEADER, usize::MAX);
+        match Machine::load(snapshot.as_bytes()) {
+            Err(SnapshotError::Parse { line: 2, message }) => assert!(message.contains("beyond the memory limit")),
+            other => panic!("expected a parse error, got {:?}", other.map(|machine| machine.cursor())),
+        }
+    }
+}
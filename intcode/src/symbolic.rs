@@ -0,0 +1,1046 @@
+// Symbolic execution: run a program with some of its memory cells or inputs
+// left as unknowns, and find values for them that make the program do
+// something, such as print a given value.
+//
+// Every value is a polynomial over the unknowns with integer coefficients,
+// so `Add` and `Multiply` are exact. A comparison or conditional jump that
+// depends on an unknown forks the run in two, each side remembering the
+// condition it assumed; sides that can't happen given the unknowns' ranges
+// are dropped. Reading memory at an address that depends on an unknown
+// gives a new unknown standing for "whatever was there", which is worked out
+// once the others have values.
+//
+// Some things can't be followed symbolically: an opcode, jump target or
+// write address that depends on an unknown, or moving the relative base by
+// an unknown amount. Runs that get there are abandoned and reported.
+//
+// To solve, the conditions on a run are handed to a small search: an
+// unknown that appears only linearly in an equation is solved for directly,
+// and the rest are tried across their ranges. Every answer is checked by
+// running the program for real on a `Machine` before it is returned.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use crate::error::IntcodeError;
+use crate::instruction::{Mode, OpcodeKind};
+use crate::machine::{Machine, RunState};
+use crate::memory::DEFAULT_MAX_SIZE;
+
+const DEFAULT_PATH_LIMIT: usize = 10_000;
+const DEFAULT_STEP_LIMIT: u64 = 1_000_000;
+// Most assignments the search will try for one set of conditions.
+const DEFAULT_SEARCH_LIMIT: u64 = 10_000_000;
+
+/// An unknown the search can choose a value for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Symbol {
+    /// The value a memory cell starts with.
+    Memory(usize),
+    /// The value given to the nth `Input` instruction executed, counting from 0.
+    Input(usize),
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Symbol::Memory(address) => write!(f, "m{}", address),
+            Symbol::Input(index) => write!(f, "in{}", index),
+        }
+    }
+}
+
+/// What the search is looking for.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Goal {
+    /// The program outputs this value.
+    Output(i64),
+    /// The program executes the instruction at this address.
+    Reach(usize),
+    /// The program halts with this value at the address.
+    MemoryAtHalt { address: usize, value: i64 },
+}
+
+/// Values for the symbols that reach the goal.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Solution {
+    pub values: Vec<(Symbol, i64)>,
+}
+
+impl Solution {
+    pub fn value(&self, symbol: Symbol) -> Option<i64> {
+        self.values.iter().find(|(s, _)| *s == symbol).map(|&(_, value)| value)
+    }
+}
+
+/// How a path through the program ended.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PathEnd {
+    Halted,
+    NeedsInput,
+    /// The program failed, as a `Machine` would have.
+    Failed { cursor: usize, reason: String },
+    /// The path depends on the symbols in a way that can't be followed.
+    Unsupported { cursor: usize, reason: &'static str },
+    StepLimit,
+}
+
+/// One way through the program, with everything shown as expressions over the symbols.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Path {
+    pub end: PathEnd,
+    /// What has to be true of the symbols for the program to go this way.
+    pub conditions: Vec<String>,
+    pub outputs: Vec<String>,
+    /// Memory cells whose value depends on the symbols when the path ends.
+    pub memory: Vec<(usize, String)>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SymbolicError {
+    /// More paths than the limit; the program branches too much on the symbols.
+    PathLimit(usize),
+    /// Too many values to try for the conditions on one path.
+    SearchLimit(u64),
+    /// Nothing was found, but some paths couldn't be followed; one of them was this.
+    Unsupported { cursor: usize, reason: &'static str },
+    /// A `Machine` couldn't be set up to check a solution.
+    Machine(IntcodeError),
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::PathLimit(limit) => write!(f, "more than {} paths to explore", limit),
+            SymbolicError::SearchLimit(limit) => write!(f, "more than {} values to try", limit),
+            SymbolicError::Unsupported { cursor, reason } => {
+                write!(f, "no solution found, but couldn't follow a path: {} at cursor {}", reason, cursor)
+            }
+            SymbolicError::Machine(error) => write!(f, "couldn't check a solution: {}", error),
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+impl From<IntcodeError> for SymbolicError {
+    fn from(error: IntcodeError) -> SymbolicError {
+        SymbolicError::Machine(error)
+    }
+}
+
+// A polynomial: each term is a product of variables (sorted, repeated for
+// powers; empty for the constant) and its coefficient, which is never 0.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+struct Expr {
+    terms: BTreeMap<Vec<usize>, i64>,
+}
+
+impl Expr {
+    fn constant(value: i64) -> Expr {
+        let mut terms = BTreeMap::new();
+        if value != 0 {
+            terms.insert(Vec::new(), value);
+        }
+        Expr { terms }
+    }
+
+    fn variable(id: usize) -> Expr {
+        let mut terms = BTreeMap::new();
+        terms.insert(vec![id], 1);
+        Expr { terms }
+    }
+
+    fn as_constant(&self) -> Option<i64> {
+        match self.terms.len() {
+            0 => Some(0),
+            1 => self.terms.get(&Vec::new()).copied(),
+            _ => None,
+        }
+    }
+
+    fn add_term(&mut self, monomial: Vec<usize>, coefficient: i64) -> Option<()> {
+        let sum = self.terms.get(&monomial).copied().unwrap_or(0).checked_add(coefficient)?;
+        if sum == 0 {
+            self.terms.remove(&monomial);
+        } else {
+            self.terms.insert(monomial, sum);
+        }
+        Some(())
+    }
+
+    fn add(&self, other: &Expr) -> Option<Expr> {
+        let mut sum = self.clone();
+        for (monomial, &coefficient) in &other.terms {
+            sum.add_term(monomial.clone(), coefficient)?;
+        }
+        Some(sum)
+    }
+
+    fn sub(&self, other: &Expr) -> Option<Expr> {
+        let mut difference = self.clone();
+        for (monomial, &coefficient) in &other.terms {
+            difference.add_term(monomial.clone(), coefficient.checked_neg()?)?;
+        }
+        Some(difference)
+    }
+
+    fn mul(&self, other: &Expr) -> Option<Expr> {
+        let mut product = Expr::default();
+        for (a, &x) in &self.terms {
+            for (b, &y) in &other.terms {
+                let mut monomial: Vec<usize> = a.iter().chain(b).copied().collect();
+                monomial.sort_unstable();
+                product.add_term(monomial, x.checked_mul(y)?)?;
+            }
+        }
+        Some(product)
+    }
+
+    fn variables(&self) -> BTreeSet<usize> {
+        self.terms.keys().flatten().copied().collect()
+    }
+
+    // If the expression is `c * v + rest` with `rest` free of `v`, gives (c, rest).
+    fn linear_in(&self, v: usize) -> Option<(i64, Expr)> {
+        let mut coefficient = None;
+        let mut rest = Expr::default();
+        for (monomial, &value) in &self.terms {
+            match monomial.iter().filter(|&&id| id == v).count() {
+                0 => {
+                    rest.terms.insert(monomial.clone(), value);
+                }
+                1 if monomial.len() == 1 => coefficient = Some(value),
+                _ => return None,
+            }
+        }
+        coefficient.map(|coefficient| (coefficient, rest))
+    }
+}
+
+// A condition assumed on a path.
+#[derive(Debug, Clone)]
+enum Condition {
+    Zero(Expr),
+    NonZero(Expr),
+    Negative(Expr),
+    NonNegative(Expr),
+}
+
+impl Condition {
+    fn expr(&self) -> &Expr {
+        match self {
+            Condition::Zero(e) | Condition::NonZero(e) | Condition::Negative(e) | Condition::NonNegative(e) => e,
+        }
+    }
+
+    fn holds(&self, value: i64) -> bool {
+        match self {
+            Condition::Zero(_) => value == 0,
+            Condition::NonZero(_) => value != 0,
+            Condition::Negative(_) => value < 0,
+            Condition::NonNegative(_) => value >= 0,
+        }
+    }
+
+    // Could the condition hold for some value between low and high?
+    fn possible(&self, (low, high): (i128, i128)) -> bool {
+        match self {
+            Condition::Zero(_) => low <= 0 && 0 <= high,
+            Condition::NonZero(_) => !(low == 0 && high == 0),
+            Condition::Negative(_) => low < 0,
+            Condition::NonNegative(_) => high >= 0,
+        }
+    }
+}
+
+// Memory cells that differ from the program, by address.
+type Cells = BTreeMap<usize, Expr>;
+
+#[derive(Debug, Clone)]
+enum Variable {
+    Free { symbol: Symbol, domain: RangeInclusive<i64> },
+    // Whatever was in `memory` at `address` when it was read.
+    Load { address: Expr, memory: Rc<Cells> },
+}
+
+// Where the nth input comes from.
+#[derive(Debug, Clone)]
+enum InputValue {
+    Concrete(i64),
+    Symbolic(usize),
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    cursor: usize,
+    relative_base: i64,
+    cells: Rc<Cells>,
+    conditions: Vec<Condition>,
+    inputs_read: usize,
+    outputs: Vec<Expr>,
+    steps: u64,
+}
+
+// What one step of a path did.
+enum Event {
+    Next,
+    Output(Expr),
+    // The path split: this is the other side.
+    Fork(Box<State>),
+    End(PathEnd),
+}
+
+#[derive(Debug, Clone)]
+pub struct SymbolicMachine {
+    program: Vec<i64>,
+    variables: Vec<Variable>,
+    // The symbolic memory cells, and the variables they start as.
+    memory: Vec<(usize, usize)>,
+    inputs: Vec<InputValue>,
+    path_limit: usize,
+    step_limit: u64,
+    search_limit: u64,
+}
+
+impl SymbolicMachine {
+    pub fn new(program: Vec<i64>) -> SymbolicMachine {
+        SymbolicMachine {
+            program,
+            variables: Vec::new(),
+            memory: Vec::new(),
+            inputs: Vec::new(),
+            path_limit: DEFAULT_PATH_LIMIT,
+            step_limit: DEFAULT_STEP_LIMIT,
+            search_limit: DEFAULT_SEARCH_LIMIT,
+        }
+    }
+
+    /// Make the memory cell at `address` a symbol with a value in `domain`.
+    pub fn symbolic_memory(&mut self, address: usize, domain: RangeInclusive<i64>) -> Symbol {
+        let symbol = Symbol::Memory(address);
+        self.memory.push((address, self.variables.len()));
+        self.variables.push(Variable::Free { symbol, domain });
+        symbol
+    }
+
+    /// Give the program its next input.
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push(InputValue::Concrete(value));
+    }
+
+    /// Make the program's next input a symbol with a value in `domain`.
+    pub fn push_symbolic_input(&mut self, domain: RangeInclusive<i64>) -> Symbol {
+        let symbol = Symbol::Input(self.inputs.len());
+        self.inputs.push(InputValue::Symbolic(self.variables.len()));
+        self.variables.push(Variable::Free { symbol, domain });
+        symbol
+    }
+
+    /// Give up with `PathLimit` after exploring this many paths.
+    pub fn set_path_limit(&mut self, limit: usize) {
+        self.path_limit = limit;
+    }
+
+    /// End each path after this many instructions.
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = limit;
+    }
+
+    /// Give up with `SearchLimit` rather than try more values than this for one path.
+    pub fn set_search_limit(&mut self, limit: u64) {
+        self.search_limit = limit;
+    }
+
+    /// Find values for the symbols that reach `goal`, or None if there aren't any.
+    pub fn solve(&self, goal: Goal) -> Result<Option<Solution>, SymbolicError> {
+        let mut explorer = Explorer {
+            machine: self,
+            variables: self.variables.clone(),
+        };
+        let mut unsupported = None;
+        let mut solution = None;
+        explorer.explore(|explorer, state, event| {
+            let mut conditions = state.conditions.clone();
+            match (goal, event) {
+                (Goal::Output(value), Some(Event::Output(output))) => match output.sub(&Expr::constant(value)) {
+                    Some(difference) => conditions.push(Condition::Zero(difference)),
+                    None => return Ok(false),
+                },
+                (Goal::Reach(address), None) if state.cursor == address => {}
+                (Goal::MemoryAtHalt { address, value }, Some(Event::End(PathEnd::Halted))) => {
+                    match explorer.read(state, address).sub(&Expr::constant(value)) {
+                        Some(difference) => conditions.push(Condition::Zero(difference)),
+                        None => return Ok(false),
+                    }
+                }
+                (_, Some(Event::End(PathEnd::Unsupported { cursor, reason }))) => {
+                    unsupported.get_or_insert(SymbolicError::Unsupported { cursor: *cursor, reason });
+                    return Ok(false);
+                }
+                _ => return Ok(false),
+            }
+            solution = explorer.search(&conditions, |values| Ok(self.check(goal, values)?))?;
+            Ok(solution.is_some())
+        })?;
+        match (solution, unsupported) {
+            (Some(values), _) => Ok(Some(self.solution(&values))),
+            (None, Some(error)) => Err(error),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Every path through the program, as far as the symbols decide it.
+    pub fn paths(&self) -> Result<Vec<Path>, SymbolicError> {
+        let mut explorer = Explorer {
+            machine: self,
+            variables: self.variables.clone(),
+        };
+        let mut ends = Vec::new();
+        explorer.explore(|_, state, event| {
+            if let Some(Event::End(end)) = event {
+                ends.push((state.clone(), end.clone()));
+            }
+            Ok(false)
+        })?;
+        Ok(ends
+            .into_iter()
+            .map(|(state, end)| Path {
+                end,
+                conditions: state.conditions.iter().map(|c| explorer.describe_condition(c)).collect(),
+                outputs: state.outputs.iter().map(|e| explorer.describe(e)).collect(),
+                memory: state
+                    .cells
+                    .iter()
+                    .filter(|(_, e)| e.as_constant().is_none())
+                    .map(|(&address, e)| (address, explorer.describe(e)))
+                    .collect(),
+            })
+            .collect())
+    }
+
+    fn solution(&self, values: &HashMap<usize, i64>) -> Solution {
+        let values = self
+            .variables
+            .iter()
+            .enumerate()
+            .filter_map(|(id, variable)| match variable {
+                Variable::Free { symbol, domain } => Some((*symbol, values.get(&id).copied().unwrap_or(*domain.start()))),
+                Variable::Load { .. } => None,
+            })
+            .collect();
+        Solution { values }
+    }
+
+    // Run the program for real with these values and see if it reaches the goal.
+    fn check(&self, goal: Goal, values: &HashMap<usize, i64>) -> Result<bool, IntcodeError> {
+        let solution = self.solution(values);
+        let mut machine = Machine::new(self.program.clone());
+        machine.set_step_limit(Some(self.step_limit));
+        for &(address, id) in &self.memory {
+            if let Variable::Free { symbol, .. } = self.variables[id] {
+                machine.try_write(address, solution.value(symbol).unwrap_or(0))?;
+            }
+        }
+        for input in &self.inputs {
+            machine.push_input(match *input {
+                InputValue::Concrete(value) => value,
+                InputValue::Symbolic(id) => match self.variables[id] {
+                    Variable::Free { symbol, .. } => solution.value(symbol).unwrap_or(0),
+                    Variable::Load { .. } => 0,
+                },
+            });
+        }
+        Ok(match goal {
+            Goal::Output(value) => loop {
+                match machine.resume() {
+                    Ok(RunState::Output(output)) if output == value => break true,
+                    Ok(RunState::Output(_)) => {}
+                    _ => break false,
+                }
+            },
+            Goal::Reach(address) => loop {
+                if machine.cursor() == address && !machine.is_halted() {
+                    break true;
+                }
+                match machine.step() {
+                    Ok(None) | Ok(Some(RunState::Output(_))) => {}
+                    _ => break false,
+                }
+            },
+            Goal::MemoryAtHalt { address, value } => machine.run().is_ok() && machine.read(address) == value,
+        })
+    }
+}
+
+// The state of one exploration: the machine's symbols plus the loads found so far.
+struct Explorer<'a> {
+    machine: &'a SymbolicMachine,
+    variables: Vec<Variable>,
+}
+
+impl Explorer<'_> {
+    // Follow every path, calling `visit` before each step (with no event),
+    // after each output and at the end of each path. Stops when `visit` returns true.
+    fn explore<F>(&mut self, mut visit: F) -> Result<(), SymbolicError>
+    where
+        F: FnMut(&mut Explorer, &State, Option<&Event>) -> Result<bool, SymbolicError>,
+    {
+        let mut cells = Cells::new();
+        for &(address, id) in &self.machine.memory {
+            cells.insert(address, Expr::variable(id));
+        }
+        let mut pending = vec![State {
+            cursor: 0,
+            relative_base: 0,
+            cells: Rc::new(cells),
+            conditions: Vec::new(),
+            inputs_read: 0,
+            outputs: Vec::new(),
+            steps: 0,
+        }];
+        let mut paths = 0;
+        while let Some(mut state) = pending.pop() {
+            paths += 1;
+            if paths > self.machine.path_limit {
+                return Err(SymbolicError::PathLimit(self.machine.path_limit));
+            }
+            loop {
+                if visit(self, &state, None)? {
+                    return Ok(());
+                }
+                let event = if state.steps >= self.machine.step_limit {
+                    Event::End(PathEnd::StepLimit)
+                } else {
+                    self.step(&mut state)
+                };
+                match event {
+                    Event::Next => {}
+                    Event::Fork(other) => pending.push(*other),
+                    Event::Output(ref output) => {
+                        state.outputs.push(output.clone());
+                        if visit(self, &state, Some(&event))? {
+                            return Ok(());
+                        }
+                    }
+                    Event::End(_) => {
+                        if visit(self, &state, Some(&event))? {
+                            return Ok(());
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, state: &State, address: usize) -> Expr {
+        match state.cells.get(&address) {
+            Some(value) => value.clone(),
+            None => Expr::constant(self.machine.program.get(address).copied().unwrap_or(0)),
+        }
+    }
+
+    fn step(&mut self, state: &mut State) -> Event {
+        let cursor = state.cursor;
+        let unsupported = |reason| Event::End(PathEnd::Unsupported { cursor, reason });
+        let failed = |reason: &str| {
+            Event::End(PathEnd::Failed {
+                cursor,
+                reason: reason.to_string(),
+            })
+        };
+
+        let opcode_value = match self.read(state, cursor).as_constant() {
+            Some(value) => value,
+            None => return unsupported("instruction depends on the symbols"),
+        };
+        let opcode = match OpcodeKind::from_value(opcode_value % 100) {
+            Some(opcode) => opcode,
+            None => return failed("unknown opcode"),
+        };
+        let mut modes = [Mode::Position; 3];
+        let mut parameters = vec![Expr::default(); 3];
+        let mut digits = opcode_value / 100;
+        for index in 0..opcode.parameter_count() {
+            modes[index] = match Mode::from_value(digits % 10) {
+                Some(mode) => mode,
+                None => return failed("invalid parameter mode"),
+            };
+            parameters[index] = self.read(state, cursor + index + 1);
+            digits /= 10;
+        }
+        let next = cursor + opcode.parameter_count() + 1;
+
+        // The address a parameter refers to.
+        let address = |state: &State, index: usize| -> Result<Expr, Event> {
+            match modes[index] {
+                Mode::Position => Ok(parameters[index].clone()),
+                Mode::Relative => parameters[index].add(&Expr::constant(state.relative_base)).ok_or_else(|| failed("arithmetic overflow")),
+                Mode::Immediate => Err(failed("write to an immediate mode parameter")),
+            }
+        };
+        macro_rules! attempt {
+            ($e:expr) => {
+                match $e {
+                    Ok(value) => value,
+                    Err(event) => return event,
+                }
+            };
+        }
+        macro_rules! arithmetic {
+            ($e:expr) => {
+                match $e {
+                    Some(value) => value,
+                    None => return unsupported("a value outgrew an i64"),
+                }
+            };
+        }
+
+        let read = |explorer: &mut Explorer, state: &mut State, index: usize| -> Result<Expr, Event> {
+            if modes[index] == Mode::Immediate {
+                return Ok(parameters[index].clone());
+            }
+            let address = address(state, index)?;
+            match address.as_constant() {
+                Some(address) if address < 0 => Err(failed("access to negative address")),
+                Some(address) => Ok(explorer.read(state, address as usize)),
+                None => {
+                    // Whatever is at an address only the symbols decide.
+                    state.conditions.push(Condition::NonNegative(address.clone()));
+                    explorer.variables.push(Variable::Load {
+                        address,
+                        memory: Rc::clone(&state.cells),
+                    });
+                    Ok(Expr::variable(explorer.variables.len() - 1))
+                }
+            }
+        };
+        let write = |state: &mut State, index: usize, value: Expr| -> Result<(), Event> {
+            let address = match address(state, index)?.as_constant() {
+                Some(address) if address < 0 => return Err(failed("access to negative address")),
+                Some(address) if address as usize >= DEFAULT_MAX_SIZE => return Err(failed("write beyond memory limit")),
+                Some(address) => address as usize,
+                None => return Err(unsupported("write to an address that depends on the symbols")),
+            };
+            Rc::make_mut(&mut state.cells).insert(address, value);
+            Ok(())
+        };
+
+        state.steps += 1;
+        state.cursor = next;
+        match opcode {
+            OpcodeKind::Add | OpcodeKind::Multiply => {
+                let a = attempt!(read(self, state, 0));
+                let b = attempt!(read(self, state, 1));
+                let result = if opcode == OpcodeKind::Add { a.add(&b) } else { a.mul(&b) };
+                let result = match (result, a.as_constant().is_some() && b.as_constant().is_some()) {
+                    (Some(result), _) => result,
+                    (None, true) => return failed("arithmetic overflow"),
+                    (None, false) => return unsupported("a value outgrew an i64"),
+                };
+                attempt!(write(state, 2, result));
+                Event::Next
+            }
+            OpcodeKind::Input => {
+                let value = match self.machine.inputs.get(state.inputs_read) {
+                    Some(InputValue::Concrete(value)) => Expr::constant(*value),
+                    Some(InputValue::Symbolic(id)) => Expr::variable(*id),
+                    None => {
+                        state.cursor = cursor;
+                        state.steps -= 1;
+                        return Event::End(PathEnd::NeedsInput);
+                    }
+                };
+                state.inputs_read += 1;
+                attempt!(write(state, 0, value));
+                Event::Next
+            }
+            OpcodeKind::Output => Event::Output(attempt!(read(self, state, 0))),
+            OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse => {
+                let condition = attempt!(read(self, state, 0));
+                let mut taken = state.clone();
+                let target = attempt!(read(self, &mut taken, 1));
+                let target = match target.as_constant() {
+                    Some(target) if target < 0 => return failed("access to negative address"),
+                    Some(target) => target as usize,
+                    None => return unsupported("jump target depends on the symbols"),
+                };
+                taken.cursor = target;
+                let (jump, fall) = if opcode == OpcodeKind::JumpIfTrue {
+                    (Condition::NonZero(condition.clone()), Condition::Zero(condition))
+                } else {
+                    (Condition::Zero(condition.clone()), Condition::NonZero(condition))
+                };
+                self.branch(state, fall, taken, jump)
+            }
+            OpcodeKind::IsLessThan | OpcodeKind::IsEquals => {
+                let a = attempt!(read(self, state, 0));
+                let b = attempt!(read(self, state, 1));
+                if let (Some(a), Some(b)) = (a.as_constant(), b.as_constant()) {
+                    let result = if opcode == OpcodeKind::IsLessThan { a < b } else { a == b };
+                    attempt!(write(state, 2, Expr::constant(result as i64)));
+                    return Event::Next;
+                }
+                let difference = arithmetic!(a.sub(&b));
+                let (yes, no) = if opcode == OpcodeKind::IsLessThan {
+                    (Condition::Negative(difference.clone()), Condition::NonNegative(difference))
+                } else {
+                    (Condition::Zero(difference.clone()), Condition::NonZero(difference))
+                };
+                let mut other = state.clone();
+                attempt!(write(state, 2, Expr::constant(0)));
+                attempt!(write(&mut other, 2, Expr::constant(1)));
+                self.branch(state, no, other, yes)
+            }
+            OpcodeKind::AdjustRelativeBase => {
+                let offset = attempt!(read(self, state, 0));
+                match offset.as_constant().and_then(|offset| state.relative_base.checked_add(offset)) {
+                    Some(base) => state.relative_base = base,
+                    None if offset.as_constant().is_some() => return failed("arithmetic overflow"),
+                    None => return unsupported("relative base depends on the symbols"),
+                }
+                Event::Next
+            }
+            OpcodeKind::Exit => {
+                state.cursor = cursor;
+                Event::End(PathEnd::Halted)
+            }
+        }
+    }
+
+    // Continue `state` assuming `condition`, and fork `other` assuming
+    // `other_condition`, dropping whichever side can't happen.
+    fn branch(&self, state: &mut State, condition: Condition, mut other: State, other_condition: Condition) -> Event {
+        let this_possible = self.possible(&condition);
+        let other_possible = self.possible(&other_condition);
+        let constant = condition.expr().as_constant().is_some();
+        match (this_possible, other_possible) {
+            (true, false) => {
+                if !constant {
+                    state.conditions.push(condition);
+                }
+                Event::Next
+            }
+            (false, true) => {
+                if !constant {
+                    other.conditions.push(other_condition);
+                }
+                *state = other;
+                Event::Next
+            }
+            (true, true) => {
+                state.conditions.push(condition);
+                other.conditions.push(other_condition);
+                Event::Fork(Box::new(other))
+            }
+            (false, false) => Event::End(PathEnd::Unsupported {
+                cursor: state.cursor,
+                reason: "neither branch is possible",
+            }),
+        }
+    }
+
+    fn possible(&self, condition: &Condition) -> bool {
+        condition.possible(self.bounds(condition.expr()))
+    }
+
+    // Lowest and highest values an expression can take, given the symbols' ranges.
+    fn bounds(&self, expr: &Expr) -> (i128, i128) {
+        let (mut low, mut high) = (0i128, 0i128);
+        for (monomial, &coefficient) in &expr.terms {
+            let (mut a, mut b) = (coefficient as i128, coefficient as i128);
+            for &id in monomial {
+                let (x, y) = match &self.variables[id] {
+                    Variable::Free { domain, .. } => (*domain.start() as i128, *domain.end() as i128),
+                    Variable::Load { .. } => (i64::MIN as i128, i64::MAX as i128),
+                };
+                let corners = [a.saturating_mul(x), a.saturating_mul(y), b.saturating_mul(x), b.saturating_mul(y)];
+                a = *corners.iter().min().unwrap();
+                b = *corners.iter().max().unwrap();
+            }
+            low = low.saturating_add(a);
+            high = high.saturating_add(b);
+        }
+        (low, high)
+    }
+
+    // The free variables an expression depends on, looking through loads.
+    fn free_variables(&self, expr: &Expr, free: &mut BTreeSet<usize>) {
+        for id in expr.variables() {
+            match &self.variables[id] {
+                Variable::Free { .. } => {
+                    free.insert(id);
+                }
+                Variable::Load { address, memory } => {
+                    self.free_variables(address, free);
+                    for value in memory.values() {
+                        self.free_variables(value, free);
+                    }
+                }
+            }
+        }
+    }
+
+    fn evaluate(&self, expr: &Expr, values: &HashMap<usize, i64>) -> Option<i64> {
+        let mut total: i64 = 0;
+        for (monomial, &coefficient) in &expr.terms {
+            let mut term = coefficient;
+            for &id in monomial {
+                term = term.checked_mul(self.value_of(id, values)?)?;
+            }
+            total = total.checked_add(term)?;
+        }
+        Some(total)
+    }
+
+    fn value_of(&self, id: usize, values: &HashMap<usize, i64>) -> Option<i64> {
+        match &self.variables[id] {
+            Variable::Free { domain, .. } => Some(values.get(&id).copied().unwrap_or(*domain.start())),
+            Variable::Load { address, memory } => {
+                let address = self.evaluate(address, values).filter(|&address| address >= 0)? as usize;
+                match memory.get(&address) {
+                    Some(value) => self.evaluate(value, values),
+                    None => Some(self.machine.program.get(address).copied().unwrap_or(0)),
+                }
+            }
+        }
+    }
+
+    // Find values for the free variables that satisfy every condition and pass `check`.
+    fn search<F>(&self, conditions: &[Condition], mut check: F) -> Result<Option<HashMap<usize, i64>>, SymbolicError>
+    where
+        F: FnMut(&HashMap<usize, i64>) -> Result<bool, SymbolicError>,
+    {
+        let mut free = BTreeSet::new();
+        for condition in conditions {
+            self.free_variables(condition.expr(), &mut free);
+        }
+        let domain = |id: usize| match &self.variables[id] {
+            Variable::Free { domain, .. } => domain.clone(),
+            Variable::Load { .. } => unreachable!("loads aren't free"),
+        };
+
+        // An equation linear in one of the free variables, with no loads in it, gives that variable directly.
+        let solved = conditions.iter().find_map(|condition| match condition {
+            Condition::Zero(expr) if expr.variables().iter().all(|&id| free.contains(&id)) => {
+                free.iter().rev().find_map(|&id| expr.linear_in(id).map(|(coefficient, rest)| (id, coefficient, rest)))
+            }
+            _ => None,
+        });
+        let enumerated: Vec<usize> = free.iter().copied().filter(|&id| Some(id) != solved.as_ref().map(|s| s.0)).collect();
+
+        let mut count: u64 = 1;
+        for &id in &enumerated {
+            let range = domain(id);
+            let size = (*range.end() as i128 - *range.start() as i128 + 1).max(0) as u64;
+            count = count.saturating_mul(size);
+        }
+        if count > self.machine.search_limit {
+            return Err(SymbolicError::SearchLimit(self.machine.search_limit));
+        }
+        if count == 0 {
+            return Ok(None);
+        }
+
+        let mut values: HashMap<usize, i64> = enumerated.iter().map(|&id| (id, *domain(id).start())).collect();
+        loop {
+            let mut candidate = Some(values.clone());
+            if let Some((id, coefficient, rest)) = &solved {
+                // coefficient * v + rest == 0
+                candidate = self
+                    .evaluate(rest, &values)
+                    .filter(|rest| rest.checked_rem(*coefficient) == Some(0))
+                    .and_then(|rest| (-rest).checked_div(*coefficient))
+                    .filter(|v| domain(*id).contains(v))
+                    .map(|v| {
+                        let mut values = values.clone();
+                        values.insert(*id, v);
+                        values
+                    });
+            }
+            if let Some(candidate) = candidate {
+                let satisfied = conditions
+                    .iter()
+                    .all(|condition| self.evaluate(condition.expr(), &candidate).is_some_and(|value| condition.holds(value)));
+                if satisfied && check(&candidate)? {
+                    return Ok(Some(candidate));
+                }
+            }
+
+            // Step to the next combination of the enumerated variables.
+            let mut advanced = false;
+            for &id in &enumerated {
+                let value = values.get_mut(&id).unwrap();
+                if *value < *domain(id).end() {
+                    *value += 1;
+                    advanced = true;
+                    break;
+                }
+                *value = *domain(id).start();
+            }
+            if !advanced {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn describe(&self, expr: &Expr) -> String {
+        if expr.terms.is_empty() {
+            return "0".to_string();
+        }
+        let mut text = String::new();
+        // Highest degree first, the constant last.
+        let mut terms: Vec<(&Vec<usize>, &i64)> = expr.terms.iter().collect();
+        terms.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(b.0)));
+        for (monomial, &coefficient) in terms {
+            let factors: Vec<String> = monomial.iter().map(|&id| self.describe_variable(id)).collect();
+            let magnitude = coefficient.unsigned_abs();
+            let term = match (magnitude, factors.is_empty()) {
+                (_, true) => magnitude.to_string(),
+                (1, false) => factors.join("*"),
+                (_, false) => format!("{}*{}", magnitude, factors.join("*")),
+            };
+            match (text.is_empty(), coefficient < 0) {
+                (true, false) => text.push_str(&term),
+                (true, true) => text.push_str(&format!("-{}", term)),
+                (false, false) => text.push_str(&format!(" + {}", term)),
+                (false, true) => text.push_str(&format!(" - {}", term)),
+            }
+        }
+        text
+    }
+
+    fn describe_variable(&self, id: usize) -> String {
+        match &self.variables[id] {
+            Variable::Free { symbol, .. } => symbol.to_string(),
+            Variable::Load { address, .. } => format!("[{}]", self.describe(address)),
+        }
+    }
+
+    fn describe_condition(&self, condition: &Condition) -> String {
+        let (expr, relation) = match condition {
+            Condition::Zero(e) => (e, "== 0"),
+            Condition::NonZero(e) => (e, "!= 0"),
+            Condition::Negative(e) => (e, "< 0"),
+            Condition::NonNegative(e) => (e, ">= 0"),
+        };
+        format!("{} {}", self.describe(expr), relation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explorer(machine: &SymbolicMachine) -> Explorer<'_> {
+        Explorer {
+            machine,
+            variables: machine.variables.clone(),
+        }
+    }
+
+    #[test]
+    fn expressions_are_exact_until_they_outgrow_an_i64() {
+        let x = Expr::variable(0);
+        let y = Expr::variable(1);
+        let sum = x.add(&Expr::constant(3)).unwrap();
+        let product = sum.mul(&y).unwrap();
+        assert_eq!(product.terms.get(&vec![0, 1]), Some(&1));
+        assert_eq!(product.terms.get(&vec![1]), Some(&3));
+        assert_eq!(product.sub(&product).unwrap(), Expr::default());
+        assert_eq!(sum.sub(&x).unwrap().as_constant(), Some(3));
+        assert_eq!(sum.linear_in(0), Some((1, Expr::constant(3))));
+        assert_eq!(x.mul(&x).unwrap().linear_in(0), None);
+        assert_eq!(product.linear_in(0), None);
+        assert_eq!(product.variables(), vec![0, 1].into_iter().collect());
+
+        assert_eq!(Expr::constant(i64::MAX).add(&Expr::constant(1)), None);
+        assert_eq!(Expr::constant(0).sub(&Expr::constant(i64::MIN)), None);
+        assert_eq!(Expr::constant(i64::MIN).mul(&Expr::constant(-1)), None);
+    }
+
+    #[test]
+    fn search_solves_linear_equations() {
+        let mut machine = SymbolicMachine::new(vec![99]);
+        machine.push_symbolic_input(0..=100);
+        let explorer = explorer(&machine);
+        let v = Expr::variable(0);
+        let solve = |coefficient: i64, constant: i64| {
+            let expr = v.mul(&Expr::constant(coefficient)).unwrap().add(&Expr::constant(constant)).unwrap();
+            explorer.search(&[Condition::Zero(expr)], |_| Ok(true)).unwrap().map(|values| values[&0])
+        };
+        assert_eq!(solve(3, -12), Some(4));
+        assert_eq!(solve(2, -3), None);
+        assert_eq!(solve(1, 1), None);
+        assert_eq!(solve(-1, i64::MIN), None);
+    }
+
+    #[test]
+    fn comparisons_fork_on_the_symbols() {
+        // IN -> [9]; LT [9], #5 -> [10]; OUT [10]; HALT
+        let mut machine = SymbolicMachine::new(vec![3, 9, 1007, 9, 5, 10, 4, 10, 99, 0, 0]);
+        let input = machine.push_symbolic_input(0..=9);
+        let mut paths = machine.paths().unwrap();
+        paths.sort_by(|a, b| a.outputs.cmp(&b.outputs));
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].outputs, ["0"]);
+        assert_eq!(paths[0].conditions, ["in0 - 5 >= 0"]);
+        assert_eq!(paths[1].outputs, ["1"]);
+        assert_eq!(paths[1].conditions, ["in0 - 5 < 0"]);
+
+        let solution = machine.solve(Goal::Output(0)).unwrap().unwrap();
+        assert!(solution.value(input).unwrap() >= 5);
+    }
+
+    #[test]
+    fn comparing_constants_never_overflows() {
+        // LT #MIN, #MAX -> [7]; OUT [7]; HALT
+        let machine = SymbolicMachine::new(vec![1107, i64::MIN, i64::MAX, 7, 4, 7, 99, 0]);
+        let paths = machine.paths().unwrap();
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].end, PathEnd::Halted);
+        assert_eq!(paths[0].outputs, ["1"]);
+    }
+
+    #[test]
+    fn jumps_fork_on_the_symbols() {
+        // IN -> [11]; JT [11], #8; OUT #7; HALT; OUT #8; HALT
+        let mut machine = SymbolicMachine::new(vec![3, 11, 1005, 11, 8, 104, 7, 99, 104, 8, 99, 0]);
+        let input = machine.push_symbolic_input(-3..=3);
+        assert_eq!(machine.paths().unwrap().len(), 2);
+        assert_eq!(machine.solve(Goal::Output(7)).unwrap().unwrap().value(input), Some(0));
+        assert_ne!(machine.solve(Goal::Output(8)).unwrap().unwrap().value(input), Some(0));
+        assert_eq!(machine.solve(Goal::Output(9)).unwrap(), None);
+    }
+
+    #[test]
+    fn unsupported_paths_are_reported() {
+        // IN -> [6]; JT #1, [6]; HALT
+        let mut machine = SymbolicMachine::new(vec![3, 6, 105, 1, 6, 99, 0]);
+        machine.push_symbolic_input(0..=9);
+        assert_eq!(
+            machine.solve(Goal::Output(1)),
+            Err(SymbolicError::Unsupported {
+                cursor: 2,
+                reason: "jump target depends on the symbols",
+            })
+        );
+        let paths = machine.paths().unwrap();
+        assert!(matches!(paths[0].end, PathEnd::Unsupported { cursor: 2, .. }));
+    }
+
+    #[test]
+    fn checking_reports_symbols_beyond_the_memory_limit() {
+        let mut machine = SymbolicMachine::new(vec![99]);
+        machine.symbolic_memory(DEFAULT_MAX_SIZE, 0..=1);
+        assert!(matches!(
+            machine.solve(Goal::Reach(0)),
+            Err(SymbolicError::Machine(IntcodeError::MemoryLimitExceeded { .. }))
+        ));
+    }
+}
@@ -0,0 +1,282 @@
+// Threaded-code backend.
+//
+// Instead of decoding and dispatching on every step, straight-line runs of
+// instructions are translated once into blocks: arrays of (handler, decoded
+// instruction) pairs, where the handler is a function specialised for the
+// opcode. A block ends after a jump or HALT, so running one is just calling
+// each handler in turn.
+//
+// Programs can rewrite their own code, so every address covered by a block
+// is remembered. A write to any of them throws away the blocks covering it,
+// and the block being run stops there. The address is also marked as
+// volatile: blocks are never translated over it again, and the instructions
+// that cover it are left to the interpreter. Day 13, for one, computes the
+// address of each screen tile into the next instruction's parameter, and
+// would otherwise have to translate its drawing routine again on every call.
+//
+// The handlers go through the same parameter reads and writes as the
+// interpreter, so the two backends report the same errors at the same cursor.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::error::IntcodeError;
+use crate::instruction::{Decoded, OpcodeKind};
+use crate::machine::{Machine, RunState};
+
+// Blocks are only built for code below this address; anything above is interpreted.
+const TRANSLATE_LIMIT: usize = 1 << 16;
+const MAX_BLOCK_LENGTH: usize = 64;
+
+/// How a Machine executes its program. Both give the same results; the
+/// threaded backend is usually faster on programs that loop a lot.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Backend {
+    #[default]
+    Interpreter,
+    Threaded,
+}
+
+// What to do after a handler has run.
+enum Flow {
+    Next,
+    Jump(usize),
+    Stop(RunState),
+    // An Input with nothing queued: the instruction didn't run.
+    Blocked,
+}
+
+type Handler = fn(&mut Machine, &Decoded) -> Result<Flow, IntcodeError>;
+
+#[derive(Debug, Clone, Copy)]
+struct Op {
+    handler: Handler,
+    instruction: Decoded,
+    next: usize,
+}
+
+#[derive(Debug)]
+struct Block {
+    ops: Vec<Op>,
+    // One past the last address the block was translated from.
+    end: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BlockCache {
+    blocks: HashMap<usize, Arc<Block>>,
+    // Addresses covered by at least one block.
+    translated: Vec<bool>,
+    // Addresses the program has written to after translating them.
+    volatile: Vec<bool>,
+    // Set when a write threw the blocks away, so the running block stops.
+    flushed: bool,
+}
+
+impl BlockCache {
+    /// Forget the blocks that `address` is part of.
+    pub(crate) fn invalidate(&mut self, address: usize) {
+        if self.translated.get(address) != Some(&true) {
+            return;
+        }
+        if self.volatile.len() <= address {
+            self.volatile.resize(address + 1, false);
+        }
+        self.volatile[address] = true;
+        let mut stale = Vec::new();
+        self.blocks.retain(|&start, block| {
+            let covers = start <= address && address < block.end;
+            if covers {
+                stale.push(start..block.end);
+            }
+            !covers
+        });
+        for range in &stale {
+            for covered in &mut self.translated[range.clone()] {
+                *covered = false;
+            }
+        }
+        // Blocks that are left may overlap the ones just removed.
+        for (&start, block) in &self.blocks {
+            if stale.iter().any(|range| start < range.end && range.start < block.end) {
+                for covered in &mut self.translated[start..block.end] {
+                    *covered = true;
+                }
+            }
+        }
+        self.flushed = true;
+    }
+}
+
+impl Machine {
+    // `resume` for the threaded backend.
+    pub(crate) fn resume_threaded(&mut self) -> Result<RunState, IntcodeError> {
+        loop {
+            if self.halted {
+                return Ok(RunState::Halted);
+            }
+            let block = match self.block_at(self.cursor)? {
+                Some(block) => block,
+                None => {
+                    if let Some(state) = self.step()? {
+                        return Ok(state);
+                    }
+                    continue;
+                }
+            };
+            self.blocks.flushed = false;
+            for op in &block.ops {
+                self.check_limits(&op.instruction)?;
+                match (op.handler)(self, &op.instruction)? {
+                    Flow::Next => self.cursor = op.next,
+                    Flow::Jump(target) => self.cursor = target,
+                    Flow::Stop(RunState::Halted) => {
+                        self.steps += 1;
+                        return Ok(RunState::Halted);
+                    }
+                    Flow::Stop(state) => {
+                        self.steps += 1;
+                        self.cursor = op.next;
+                        return Ok(state);
+                    }
+                    Flow::Blocked => return Ok(RunState::NeedsInput),
+                }
+                self.steps += 1;
+                if self.blocks.flushed {
+                    break;
+                }
+            }
+        }
+    }
+
+    // The block starting at `start`, translating it first if needed.
+    // None if the instruction there has to be interpreted.
+    fn block_at(&mut self, start: usize) -> Result<Option<Arc<Block>>, IntcodeError> {
+        if let Some(block) = self.blocks.blocks.get(&start) {
+            return Ok(Some(Arc::clone(block)));
+        }
+        let mut ops = Vec::new();
+        let mut address = start;
+        while ops.len() < MAX_BLOCK_LENGTH && address < TRANSLATE_LIMIT {
+            let instruction = match Decoded::new(&self.memory, address) {
+                Ok(instruction) => instruction,
+                // Let the bad instruction report itself when it is reached.
+                Err(_) if !ops.is_empty() => break,
+                Err(e) => return Err(e),
+            };
+            let next = address + instruction.width();
+            let volatile = &self.blocks.volatile;
+            if (address..next).any(|covered| volatile.get(covered) == Some(&true)) {
+                break;
+            }
+            ops.push(Op {
+                handler: handler(instruction.opcode),
+                instruction,
+                next,
+            });
+            address = next;
+            if matches!(
+                instruction.opcode,
+                OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse | OpcodeKind::Exit
+            ) {
+                break;
+            }
+        }
+
+        if ops.is_empty() {
+            return Ok(None);
+        }
+        let translated = &mut self.blocks.translated;
+        if translated.len() < address {
+            translated.resize(address, false);
+        }
+        for covered in &mut translated[start..address] {
+            *covered = true;
+        }
+        let block = Arc::new(Block { ops, end: address });
+        self.blocks.blocks.insert(start, Arc::clone(&block));
+        Ok(Some(block))
+    }
+}
+
+fn handler(opcode: OpcodeKind) -> Handler {
+    match opcode {
+        OpcodeKind::Add => add,
+        OpcodeKind::Multiply => multiply,
+        OpcodeKind::Input => input,
+        OpcodeKind::Output => output,
+        OpcodeKind::JumpIfTrue => jump_if_true,
+        OpcodeKind::JumpIfFalse => jump_if_false,
+        OpcodeKind::IsLessThan => less_than,
+        OpcodeKind::IsEquals => equals,
+        OpcodeKind::AdjustRelativeBase => adjust_relative_base,
+        OpcodeKind::Exit => exit,
+    }
+}
+
+fn add(machine: &mut Machine, instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    let result = machine.read_parameter(instruction, 0)?.checked_add(machine.read_parameter(instruction, 1)?);
+    let result = machine.checked(instruction, result)?;
+    machine.write_parameter(instruction, 2, result)?;
+    Ok(Flow::Next)
+}
+
+fn multiply(machine: &mut Machine, instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    let result = machine.read_parameter(instruction, 0)?.checked_mul(machine.read_parameter(instruction, 1)?);
+    let result = machine.checked(instruction, result)?;
+    machine.write_parameter(instruction, 2, result)?;
+    Ok(Flow::Next)
+}
+
+fn input(machine: &mut Machine, instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    match machine.inputs.pop_front() {
+        Some(value) => {
+            machine.write_parameter(instruction, 0, value)?;
+            Ok(Flow::Next)
+        }
+        None => Ok(Flow::Blocked),
+    }
+}
+
+fn output(machine: &mut Machine, instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    Ok(Flow::Stop(RunState::Output(machine.read_parameter(instruction, 0)?)))
+}
+
+fn jump_if_true(machine: &mut Machine, instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    if machine.read_parameter(instruction, 0)? != 0 {
+        Ok(Flow::Jump(machine.jump_target(instruction)?))
+    } else {
+        Ok(Flow::Next)
+    }
+}
+
+fn jump_if_false(machine: &mut Machine, instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    if machine.read_parameter(instruction, 0)? == 0 {
+        Ok(Flow::Jump(machine.jump_target(instruction)?))
+    } else {
+        Ok(Flow::Next)
+    }
+}
+
+fn less_than(machine: &mut Machine, instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    let result = machine.read_parameter(instruction, 0)? < machine.read_parameter(instruction, 1)?;
+    machine.write_parameter(instruction, 2, result as i64)?;
+    Ok(Flow::Next)
+}
+
+fn equals(machine: &mut Machine, instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    let result = machine.read_parameter(instruction, 0)? == machine.read_parameter(instruction, 1)?;
+    machine.write_parameter(instruction, 2, result as i64)?;
+    Ok(Flow::Next)
+}
+
+fn adjust_relative_base(machine: &mut Machine, instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    let base = machine.relative_base.checked_add(machine.read_parameter(instruction, 0)?);
+    machine.relative_base = machine.checked(instruction, base)?;
+    Ok(Flow::Next)
+}
+
+fn exit(machine: &mut Machine, _instruction: &Decoded) -> Result<Flow, IntcodeError> {
+    machine.halted = true;
+    Ok(Flow::Stop(RunState::Halted))
+}
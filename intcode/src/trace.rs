@@ -0,0 +1,360 @@
+// Execution traces: a record of every instruction a machine executed.
+//
+// Traces are written as JSON lines, one event per executed instruction. For
+// `3,5,4,5,99,0` given the input 5:
+//
+//     {"step":0,"cursor":0,"opcode":3,"rb":0,"operands":[0],"write":[5,5],"input":5}
+//     {"step":1,"cursor":2,"opcode":4,"rb":0,"operands":[5],"output":5}
+//
+// `operands` holds the value of each parameter as the instruction saw it
+// (the address itself for a write target in position or relative mode is
+// in `write`, along with the value written). `write`, `input` and `output`
+// are left out when the instruction didn't do any of those.
+//
+// `replay` runs a program again, feeding it the inputs from a trace, and
+// reports the first step where the live run does something different.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::error::IntcodeError;
+use crate::machine::{Machine, RunState};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceEvent {
+    pub step: u64,
+    pub cursor: usize,
+    pub opcode_value: i64,
+    /// The relative base before the instruction ran.
+    pub relative_base: i64,
+    pub operands: Vec<i64>,
+    /// Address and value of the memory write, if any.
+    pub write: Option<(usize, i64)>,
+    pub input: Option<i64>,
+    pub output: Option<i64>,
+}
+
+impl TraceEvent {
+    pub fn to_json(&self) -> String {
+        let operands: Vec<String> = self.operands.iter().map(|v| v.to_string()).collect();
+        let mut json = format!(
+            "{{\"step\":{},\"cursor\":{},\"opcode\":{},\"rb\":{},\"operands\":[{}]",
+            self.step,
+            self.cursor,
+            self.opcode_value,
+            self.relative_base,
+            operands.join(",")
+        );
+        if let Some((address, value)) = self.write {
+            json.push_str(&format!(",\"write\":[{},{}]", address, value));
+        }
+        if let Some(input) = self.input {
+            json.push_str(&format!(",\"input\":{}", input));
+        }
+        if let Some(output) = self.output {
+            json.push_str(&format!(",\"output\":{}", output));
+        }
+        json.push('}');
+        json
+    }
+
+    /// Parse one line written by `to_json`. Keys may come in any order.
+    pub fn from_json(line: &str) -> Result<TraceEvent, String> {
+        let body = line
+            .trim()
+            .strip_prefix('{')
+            .and_then(|rest| rest.strip_suffix('}'))
+            .ok_or("expected a JSON object")?;
+        let mut fields = Fields { rest: body };
+        let mut event = TraceEvent {
+            step: 0,
+            cursor: 0,
+            opcode_value: 0,
+            relative_base: 0,
+            operands: Vec::new(),
+            write: None,
+            input: None,
+            output: None,
+        };
+        let mut seen = Vec::new();
+        while let Some((key, value)) = fields.next_field()? {
+            match key {
+                "step" => event.step = parse_number(value)?,
+                "cursor" => event.cursor = parse_number(value)?,
+                "opcode" => event.opcode_value = parse_number(value)?,
+                "rb" => event.relative_base = parse_number(value)?,
+                "operands" => event.operands = parse_array(value)?,
+                "write" => match parse_array(value)?[..] {
+                    [address, value] if address >= 0 => event.write = Some((address as usize, value)),
+                    _ => return Err(format!("bad write {}", value)),
+                },
+                "input" => event.input = Some(parse_number(value)?),
+                "output" => event.output = Some(parse_number(value)?),
+                _ => return Err(format!("unknown key {}", key)),
+            }
+            seen.push(key);
+        }
+        for key in &["step", "cursor", "opcode", "rb", "operands"] {
+            if !seen.contains(key) {
+                return Err(format!("missing key {}", key));
+            }
+        }
+        Ok(event)
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "step {} at {:04}: opcode {} {:?}", self.step, self.cursor, self.opcode_value, self.operands)?;
+        if let Some((address, value)) = self.write {
+            write!(f, ", [{}] = {}", address, value)?;
+        }
+        if let Some(input) = self.input {
+            write!(f, ", input {}", input)?;
+        }
+        if let Some(output) = self.output {
+            write!(f, ", output {}", output)?;
+        }
+        Ok(())
+    }
+}
+
+// Splits the inside of a flat JSON object into `"key":value` pairs.
+struct Fields<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Fields<'a> {
+    fn next_field(&mut self) -> Result<Option<(&'a str, &'a str)>, String> {
+        let rest = self.rest.trim_start().trim_start_matches(',').trim_start();
+        if rest.is_empty() {
+            return Ok(None);
+        }
+        let rest = rest.strip_prefix('"').ok_or("expected a key")?;
+        let end = rest.find('"').ok_or("unterminated key")?;
+        let key = &rest[..end];
+        let rest = rest[end + 1..].trim_start().strip_prefix(':').ok_or("expected ':'")?.trim_start();
+        let end = if rest.starts_with('[') {
+            rest.find(']').ok_or("unterminated array")? + 1
+        } else {
+            rest.find(',').unwrap_or(rest.len())
+        };
+        self.rest = &rest[end..];
+        Ok(Some((key, rest[..end].trim())))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("bad number {}", text))
+}
+
+fn parse_array(text: &str) -> Result<Vec<i64>, String> {
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .ok_or(format!("expected an array, found {}", text))?
+        .trim();
+    if inner.is_empty() {
+        return Ok(Vec::new());
+    }
+    inner.split(',').map(|value| parse_number(value.trim())).collect()
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TraceError::Io(e) => write!(f, "{}", e),
+            TraceError::Parse { line, message } => write!(f, "trace line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for TraceError {}
+
+impl From<io::Error> for TraceError {
+    fn from(e: io::Error) -> TraceError {
+        TraceError::Io(e)
+    }
+}
+
+/// Write a trace as JSON lines.
+pub fn write_trace<W: Write>(mut writer: W, events: &[TraceEvent]) -> io::Result<()> {
+    for event in events {
+        writeln!(writer, "{}", event.to_json())?;
+    }
+    Ok(())
+}
+
+/// Read a trace written by `write_trace`. Blank lines are skipped.
+pub fn read_trace<R: BufRead>(reader: R) -> Result<Vec<TraceEvent>, TraceError> {
+    let mut events = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = TraceEvent::from_json(&line).map_err(|message| TraceError::Parse {
+            line: index + 1,
+            message,
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+// The first point where a live run and a recorded trace disagree.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub step: u64,
+    /// What the trace says happened, or None if the live run went on longer.
+    pub expected: Option<TraceEvent>,
+    /// What the live run did, or None if it stopped early.
+    pub actual: Option<TraceEvent>,
+    /// Why the live run stopped early, if it failed.
+    pub error: Option<IntcodeError>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "runs diverge at step {}", self.step)?;
+        match &self.expected {
+            Some(event) => writeln!(f, "  trace: {}", event)?,
+            None => writeln!(f, "  trace: (ended)")?,
+        }
+        match (&self.actual, &self.error) {
+            (Some(event), _) => write!(f, "  live:  {}", event),
+            (None, Some(error)) => write!(f, "  live:  {}", error),
+            (None, None) => write!(f, "  live:  (stopped)"),
+        }
+    }
+}
+
+/// Run `program` with the inputs recorded in `trace` and compare every
+/// executed instruction against it. The trace has to start from the
+/// beginning of the program. Returns None if the runs match exactly.
+pub fn replay(program: Vec<i64>, trace: &[TraceEvent]) -> Option<Divergence> {
+    let mut machine = Machine::new(program);
+    for input in trace.iter().filter_map(|event| event.input) {
+        machine.push_input(input);
+    }
+    // One step more than the trace, to notice a live run that keeps going.
+    machine.set_step_limit(Some(trace.len() as u64 + 1));
+    machine.start_trace();
+    let mut error = None;
+    loop {
+        match machine.resume() {
+            Ok(RunState::Output(_)) => {}
+            Ok(_) => break,
+            Err(IntcodeError::StepLimitExceeded { .. }) => break,
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        }
+    }
+    let live = machine.stop_trace();
+
+    for step in 0..trace.len().max(live.len()) {
+        let expected = trace.get(step);
+        let actual = live.get(step);
+        if expected != actual {
+            return Some(Divergence {
+                step: step as u64,
+                expected: expected.cloned(),
+                actual: actual.cloned(),
+                error: if actual.is_none() { error } else { None },
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Echoes one input.
+    const ECHO: [i64; 6] = [3, 5, 4, 5, 99, 0];
+
+    fn record(program: &[i64], inputs: &[i64]) -> Vec<TraceEvent> {
+        let mut machine = Machine::new(program.to_vec());
+        for &input in inputs {
+            machine.push_input(input);
+        }
+        machine.start_trace();
+        while let Ok(RunState::Output(_)) = machine.resume() {}
+        machine.stop_trace()
+    }
+
+    #[test]
+    fn events_are_written_as_json_lines() {
+        let mut text = Vec::new();
+        write_trace(&mut text, &record(&ECHO, &[5])).unwrap();
+        let expected = "\
+{\"step\":0,\"cursor\":0,\"opcode\":3,\"rb\":0,\"operands\":[0],\"write\":[5,5],\"input\":5}
+{\"step\":1,\"cursor\":2,\"opcode\":4,\"rb\":0,\"operands\":[5],\"output\":5}
+{\"step\":2,\"cursor\":4,\"opcode\":99,\"rb\":0,\"operands\":[]}
+";
+        assert_eq!(String::from_utf8(text).unwrap(), expected);
+    }
+
+    #[test]
+    fn events_read_back_in_any_key_order() {
+        let events = record(&ECHO, &[-7]);
+        for event in &events {
+            assert_eq!(TraceEvent::from_json(&event.to_json()).as_ref(), Ok(event));
+        }
+        let shuffled = "{ \"operands\": [ 1, 2 ], \"rb\": -3, \"output\": 4, \"opcode\": 204, \"cursor\": 9, \"step\": 12 }";
+        let event = TraceEvent::from_json(shuffled).unwrap();
+        assert_eq!((event.step, event.cursor, event.relative_base), (12, 9, -3));
+        assert_eq!((event.operands, event.output, event.write), (vec![1, 2], Some(4), None));
+    }
+
+    #[test]
+    fn malformed_events_are_rejected() {
+        let missing = "{\"step\":0,\"cursor\":0,\"opcode\":99,\"rb\":0}";
+        assert_eq!(TraceEvent::from_json(missing), Err("missing key operands".to_string()));
+        let unknown = "{\"step\":0,\"cursor\":0,\"opcode\":99,\"rb\":0,\"operands\":[],\"halt\":1}";
+        assert_eq!(TraceEvent::from_json(unknown), Err("unknown key halt".to_string()));
+        let write = "{\"step\":0,\"cursor\":0,\"opcode\":1,\"rb\":0,\"operands\":[],\"write\":[-1,0]}";
+        assert!(TraceEvent::from_json(write).is_err());
+        assert!(TraceEvent::from_json("[1, 2]").is_err());
+
+        let text = "\n{\"step\":0,\"cursor\":0,\"opcode\":99,\"rb\":0,\"operands\":[]}\n\n{\"step\":x}\n";
+        match read_trace(text.as_bytes()) {
+            Err(TraceError::Parse { line, .. }) => assert_eq!(line, 4),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn replay_finds_the_first_difference() {
+        let trace = record(&ECHO, &[5]);
+        assert!(replay(ECHO.to_vec(), &trace).is_none());
+
+        // Outputting a constant instead differs at the output.
+        let changed = vec![3, 5, 104, 6, 99, 0];
+        let divergence = replay(changed, &trace).unwrap();
+        assert_eq!(divergence.step, 1);
+        assert_eq!(divergence.actual.map(|event| event.output), Some(Some(6)));
+
+        // A trace cut short is noticed where it ends.
+        let divergence = replay(ECHO.to_vec(), &trace[..2]).unwrap();
+        assert_eq!(divergence.step, 2);
+        assert!(divergence.expected.is_none());
+        assert_eq!(divergence.actual.map(|event| event.opcode_value), Some(99));
+
+        // A live run that fails reports why.
+        let divergence = replay(vec![3, 5, 4, 5, 98, 0], &trace).unwrap();
+        assert_eq!(divergence.step, 2);
+        assert!(divergence.actual.is_none());
+        assert!(matches!(divergence.error, Some(IntcodeError::UnknownOpcode { .. })));
+    }
+}
@@ -0,0 +1,339 @@
+// Ahead-of-time compiler: turns an Intcode program into a standalone Rust
+// source file.
+//
+// Every instruction the disassembler finds becomes one arm of a `match` on
+// the program counter, with its operands resolved in place: immediates
+// become constants, position and relative mode become memory accesses at a
+// fixed address or a fixed offset from the relative base. Anything else, such
+// as a jump into code the disassembler didn't find, is run by a small
+// interpreter embedded in the output.
+//
+// An arm is only used while the memory it was compiled from is unchanged.
+// A write to an address inside a compiled instruction switches that
+// instruction over to the interpreter for the rest of the run, so
+// self-modifying programs still behave exactly as they do on a `Machine`.
+//
+// The generated program takes its inputs from the command line, then from
+// stdin one per line, prints each output on its own line, and reports errors
+// the same way the `Machine` does.
+
+use std::fmt::Write;
+
+use crate::disasm::CodeMap;
+use crate::instruction::{Instruction, Mode, OpcodeKind};
+use crate::memory::DEFAULT_MAX_SIZE;
+
+// Everything in the generated file that doesn't depend on the program.
+const RUNTIME: &str = r#"use std::collections::VecDeque;
+use std::env;
+use std::io::{self, BufRead, BufWriter, Stdout, Write};
+use std::process;
+
+struct Vm {
+    memory: Vec<i64>,
+    rb: i64,
+    // Instructions whose compiled arm can still be used, by address.
+    compiled: Vec<bool>,
+    inputs: VecDeque<i64>,
+    stdout: BufWriter<Stdout>,
+}
+
+impl Vm {
+    fn new() -> Vm {
+        let mut compiled = vec![false; PROGRAM.len()];
+        for &address in COMPILED.iter() {
+            compiled[address] = true;
+        }
+        let mut inputs = VecDeque::new();
+        for arg in env::args().skip(1) {
+            match arg.parse() {
+                Ok(value) => inputs.push_back(value),
+                Err(_) => {
+                    println!("usage: {} [input...]", env::args().next().unwrap_or_default());
+                    process::exit(2);
+                }
+            }
+        }
+        Vm {
+            memory: PROGRAM.to_vec(),
+            rb: 0,
+            compiled,
+            inputs,
+            stdout: BufWriter::new(io::stdout()),
+        }
+    }
+
+    fn fail(&mut self, message: String, at: usize) -> ! {
+        let _ = self.stdout.flush();
+        println!("Application error: {} (opcode {} at cursor {})", message, self.get(at), at);
+        process::exit(1);
+    }
+
+    fn get(&self, address: usize) -> i64 {
+        self.memory.get(address).copied().unwrap_or(0)
+    }
+
+    fn check(&mut self, address: i64, at: usize) -> usize {
+        if address < 0 {
+            self.fail(format!("access to negative address {}", address), at);
+        }
+        address as usize
+    }
+
+    fn read(&mut self, address: i64, at: usize) -> i64 {
+        let address = self.check(address, at);
+        self.get(address)
+    }
+
+    fn write(&mut self, address: i64, value: i64, at: usize) {
+        let address = self.check(address, at);
+        if address >= MAX_MEMORY {
+            self.fail(format!("write to address {} beyond memory limit {}", address, MAX_MEMORY), at);
+        }
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+        }
+        self.memory[address] = value;
+        // Stop using any compiled instruction this write lands in.
+        for start in address.saturating_sub(3)..=address {
+            if let Some(compiled) = self.compiled.get_mut(start) {
+                *compiled = false;
+            }
+        }
+    }
+
+    fn relative(&mut self, offset: i64, at: usize) -> i64 {
+        match self.rb.checked_add(offset) {
+            Some(address) => address,
+            None => self.fail("arithmetic overflow".to_string(), at),
+        }
+    }
+
+    fn arithmetic(&mut self, result: Option<i64>, at: usize) -> i64 {
+        match result {
+            Some(result) => result,
+            None => self.fail("arithmetic overflow".to_string(), at),
+        }
+    }
+
+    fn target(&mut self, target: i64, at: usize) -> usize {
+        self.check(target, at)
+    }
+
+    fn input(&mut self, at: usize) -> i64 {
+        if let Some(value) = self.inputs.pop_front() {
+            return value;
+        }
+        let _ = self.stdout.flush();
+        let mut line = String::new();
+        let value = match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => line.trim().parse().ok(),
+        };
+        match value {
+            Some(value) => value,
+            None => self.fail("input requested but none available".to_string(), at),
+        }
+    }
+
+    fn output(&mut self, value: i64) {
+        let _ = writeln!(self.stdout, "{}", value);
+    }
+
+    // The interpreter, for instructions that weren't compiled or have been
+    // written over. Runs one instruction; returns the next address, or None
+    // at a halt.
+    fn step(&mut self, at: usize) -> Option<usize> {
+        let opcode_value = self.get(at);
+        let count = match opcode_value % 100 {
+            1 | 2 | 7 | 8 => 3,
+            5 | 6 => 2,
+            3 | 4 | 9 => 1,
+            99 => 0,
+            _ => self.fail("unknown opcode".to_string(), at),
+        };
+        let mut modes = [0; 3];
+        let mut digits = opcode_value / 100;
+        for mode in modes.iter_mut().take(count) {
+            *mode = digits % 10;
+            if *mode > 2 {
+                self.fail(format!("invalid parameter mode {}", mode), at);
+            }
+            digits /= 10;
+        }
+        let parameters = [self.get(at + 1), self.get(at + 2), self.get(at + 3)];
+        let next = at + count + 1;
+        match opcode_value % 100 {
+            1 => {
+                let (a, b) = (self.parameter(&modes, &parameters, 0, at), self.parameter(&modes, &parameters, 1, at));
+                let value = self.arithmetic(a.checked_add(b), at);
+                self.store(&modes, &parameters, 2, value, at);
+            }
+            2 => {
+                let (a, b) = (self.parameter(&modes, &parameters, 0, at), self.parameter(&modes, &parameters, 1, at));
+                let value = self.arithmetic(a.checked_mul(b), at);
+                self.store(&modes, &parameters, 2, value, at);
+            }
+            3 => {
+                let value = self.input(at);
+                self.store(&modes, &parameters, 0, value, at);
+            }
+            4 => {
+                let value = self.parameter(&modes, &parameters, 0, at);
+                self.output(value);
+            }
+            5 | 6 => {
+                let condition = self.parameter(&modes, &parameters, 0, at) != 0;
+                if condition == (opcode_value % 100 == 5) {
+                    let target = self.parameter(&modes, &parameters, 1, at);
+                    return Some(self.target(target, at));
+                }
+            }
+            7 | 8 => {
+                let (a, b) = (self.parameter(&modes, &parameters, 0, at), self.parameter(&modes, &parameters, 1, at));
+                let value = if opcode_value % 100 == 7 { a < b } else { a == b };
+                self.store(&modes, &parameters, 2, value as i64, at);
+            }
+            9 => {
+                let offset = self.parameter(&modes, &parameters, 0, at);
+                self.rb = self.relative(offset, at);
+            }
+            _ => return None,
+        }
+        Some(next)
+    }
+
+    fn address(&mut self, modes: &[i64; 3], parameters: &[i64; 3], index: usize, at: usize) -> i64 {
+        match modes[index] {
+            0 => parameters[index],
+            2 => self.relative(parameters[index], at),
+            _ => self.fail("write to an immediate mode parameter".to_string(), at),
+        }
+    }
+
+    fn parameter(&mut self, modes: &[i64; 3], parameters: &[i64; 3], index: usize, at: usize) -> i64 {
+        match modes[index] {
+            1 => parameters[index],
+            _ => {
+                let address = self.address(modes, parameters, index, at);
+                self.read(address, at)
+            }
+        }
+    }
+
+    fn store(&mut self, modes: &[i64; 3], parameters: &[i64; 3], index: usize, value: i64, at: usize) {
+        let address = self.address(modes, parameters, index, at);
+        self.write(address, value, at);
+    }
+}
+"#;
+
+/// Rust source for a standalone program that runs `program`.
+pub fn transpile(program: &[i64]) -> String {
+    let code = CodeMap::new(program);
+    let mut rust = String::new();
+    let _ = writeln!(rust, "// Compiled from an Intcode program by `intcode compile`.");
+    let _ = writeln!(rust, "#![allow(unused_parens, unused_variables, unreachable_code, clippy::all)]\n");
+    let _ = writeln!(rust, "{}", RUNTIME);
+
+    let values: Vec<String> = program.iter().map(|value| value.to_string()).collect();
+    let _ = writeln!(rust, "const PROGRAM: [i64; {}] = [{}];", program.len(), values.join(", "));
+    let compiled: Vec<String> = code.instructions().map(|(address, _)| address.to_string()).collect();
+    let _ = writeln!(rust, "const COMPILED: [usize; {}] = [{}];", compiled.len(), compiled.join(", "));
+    let _ = writeln!(rust, "const MAX_MEMORY: usize = {};\n", DEFAULT_MAX_SIZE.max(program.len()));
+
+    let _ = writeln!(rust, "fn main() {{");
+    let _ = writeln!(rust, "    let mut vm = Vm::new();");
+    let _ = writeln!(rust, "    let mut pc: usize = 0;");
+    let _ = writeln!(rust, "    loop {{");
+    let _ = writeln!(rust, "        match pc {{");
+    for (address, instruction) in code.instructions() {
+        let _ = writeln!(rust, "            {} if vm.compiled[{}] => {{", address, address);
+        for line in compile(address, instruction) {
+            let _ = writeln!(rust, "                {}", line);
+        }
+        let _ = writeln!(rust, "            }}");
+    }
+    let _ = writeln!(rust, "            _ => match vm.step(pc) {{");
+    let _ = writeln!(rust, "                Some(next) => pc = next,");
+    let _ = writeln!(rust, "                None => break,");
+    let _ = writeln!(rust, "            }},");
+    let _ = writeln!(rust, "        }}");
+    let _ = writeln!(rust, "    }}");
+    let _ = writeln!(rust, "    let _ = vm.stdout.flush();");
+    let _ = writeln!(rust, "}}");
+    rust
+}
+
+// The body of the match arm for one instruction.
+fn compile(at: usize, instruction: &Instruction) -> Vec<String> {
+    let next = at + instruction.width();
+    let read = |index: usize| read_parameter(at, instruction, index);
+    let write = |index: usize, value: &str| write_parameter(at, instruction, index, value);
+    match instruction.opcode {
+        OpcodeKind::Add | OpcodeKind::Multiply => {
+            let method = if instruction.opcode == OpcodeKind::Add { "checked_add" } else { "checked_mul" };
+            vec![
+                format!("let (a, b) = ({}, {});", read(0), read(1)),
+                format!("let value = vm.arithmetic(a.{}(b), {});", method, at),
+                write(2, "value"),
+                format!("pc = {};", next),
+            ]
+        }
+        OpcodeKind::IsLessThan | OpcodeKind::IsEquals => {
+            let operator = if instruction.opcode == OpcodeKind::IsLessThan { "<" } else { "==" };
+            vec![
+                format!("let (a, b) = ({}, {});", read(0), read(1)),
+                write(2, &format!("(a {} b) as i64", operator)),
+                format!("pc = {};", next),
+            ]
+        }
+        OpcodeKind::Input => vec![
+            format!("let value = vm.input({});", at),
+            write(0, "value"),
+            format!("pc = {};", next),
+        ],
+        OpcodeKind::Output => vec![
+            format!("let value = {};", read(0)),
+            "vm.output(value);".to_string(),
+            format!("pc = {};", next),
+        ],
+        OpcodeKind::JumpIfTrue | OpcodeKind::JumpIfFalse => {
+            let operator = if instruction.opcode == OpcodeKind::JumpIfTrue { "!=" } else { "==" };
+            vec![
+                format!("if {} {} 0 {{", read(0), operator),
+                format!("    let target = {};", read(1)),
+                format!("    pc = vm.target(target, {});", at),
+                "} else {".to_string(),
+                format!("    pc = {};", next),
+                "}".to_string(),
+            ]
+        }
+        OpcodeKind::AdjustRelativeBase => vec![
+            format!("let offset = {};", read(0)),
+            format!("vm.rb = vm.relative(offset, {});", at),
+            format!("pc = {};", next),
+        ],
+        OpcodeKind::Exit => vec!["break;".to_string()],
+    }
+}
+
+// An expression for the value of a parameter.
+fn read_parameter(at: usize, instruction: &Instruction, index: usize) -> String {
+    let parameter = instruction.parameters[index];
+    match instruction.modes[index] {
+        Mode::Immediate => format!("({}i64)", parameter),
+        Mode::Position => format!("vm.read({}, {})", parameter, at),
+        Mode::Relative => format!("({{ let address = vm.relative({}, {}); vm.read(address, {}) }})", parameter, at, at),
+    }
+}
+
+// A statement writing `value` to the address a parameter refers to.
+fn write_parameter(at: usize, instruction: &Instruction, index: usize, value: &str) -> String {
+    let parameter = instruction.parameters[index];
+    match instruction.modes[index] {
+        Mode::Position => format!("vm.write({}, {}, {});", parameter, value, at),
+        Mode::Relative => format!("let address = vm.relative({}, {}); vm.write(address, {}, {});", parameter, at, value, at),
+        Mode::Immediate => format!("vm.fail(\"write to an immediate mode parameter\".to_string(), {});", at),
+    }
+}
@@ -0,0 +1,695 @@
+// Differential fuzzing: random programs run every way the crate can run
+// them, which must all agree on the outputs, the error (if any), the final
+// memory and registers, and whether the machine halted.
+//
+// The programs are random but always terminate: the only backward jumps
+// close counted loops whose counters the rest of the program can't touch.
+// They read and write a data area through position and relative mode,
+// adjust the relative base, skip forward over instructions, overwrite the
+// immediate operands of other instructions (including ones already
+// translated by the threaded backend), and now and then go wrong on purpose
+// with a bad opcode, a negative address or an overflowing calculation.
+//
+// The same kind of programs also run on the machines each day had before
+// this crate (see legacy/mod.rs): the day 5 functions, the day 7 amplifier,
+// the day 9 and day 11 compilers and the day 13 arcade game. Days 5 and 7
+// get programs without relative mode whose values fit in an i32. Those
+// machines fail by panicking, which has to happen exactly where the crate's
+// machine returns an error; their overflow panics only count in debug builds.
+//
+// Set INTCODE_FUZZ_CASES to run more programs, and INTCODE_FUZZ_SEED to
+// start from a different seed. A failure names the seed that reproduces it.
+
+mod legacy;
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use intcode::{replay, Backend, History, IntcodeError, Limits, Machine, RunState};
+
+const DEFAULT_CASES: u64 = 300;
+// Inputs queued for every run; programs never read this many.
+const INPUTS: usize = 4096;
+// Every run is cut off here; generated programs never get near it.
+const MAX_STEPS: u64 = 1_000_000;
+// Size of the area the program reads and writes, and how far the relative
+// base moves around inside it.
+const DATA_SIZE: i64 = 48;
+const BASE_RANGE: i64 = 16;
+// Loop counters, one per level of nesting, live after the data.
+const MAX_DEPTH: usize = 2;
+
+// xorshift64*, so the tests need nothing outside std.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low + 1) as u64) as i64
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+// A parameter before layout. `Code` is the address of another instruction,
+// by its index, and `Data` an offset into the data area.
+#[derive(Debug, Copy, Clone)]
+enum Param {
+    Immediate(i64),
+    Data(i64),
+    Relative(i64),
+    Code(usize),
+    Counter(usize),
+    // Position mode, straight to a raw address.
+    Address(i64),
+    // Position mode, at the `slot`th patchable immediate.
+    Patch(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Op {
+    opcode: i64,
+    params: Vec<Param>,
+    // Which parameters are immediates the program may overwrite.
+    patchable: Vec<bool>,
+}
+
+impl Op {
+    fn new(opcode: i64, params: Vec<Param>) -> Op {
+        let patchable = vec![false; params.len()];
+        Op { opcode, params, patchable }
+    }
+}
+
+struct Generator {
+    rng: Rng,
+    ops: Vec<Op>,
+    // The relative base's offset into the data area at this point in the code.
+    base: i64,
+    depth: usize,
+    // Nonzero inside code that might be skipped.
+    fixed_base: usize,
+    patches: usize,
+    dialect: Dialect,
+}
+
+// What the machine running a program understands. Days 5 and 7 had no
+// relative mode and kept memory in i32s.
+#[derive(Debug, Copy, Clone)]
+struct Dialect {
+    relative_mode: bool,
+    smallest: i64,
+    largest: i64,
+}
+
+const FULL: Dialect = Dialect {
+    relative_mode: true,
+    smallest: i64::MIN,
+    largest: i64::MAX,
+};
+const I32: Dialect = Dialect {
+    relative_mode: false,
+    smallest: i32::MIN as i64,
+    largest: i32::MAX as i64,
+};
+
+impl Generator {
+    fn value(&mut self) -> i64 {
+        match self.rng.below(200) {
+            0 => self.dialect.largest / self.rng.range(1, 4),
+            1 => self.dialect.smallest / self.rng.range(1, 4),
+            2..=19 => self.rng.range(-100_000, 100_000),
+            _ => self.rng.range(-10, 10),
+        }
+    }
+
+    fn read(&mut self) -> Param {
+        match self.rng.below(if self.dialect.relative_mode { 3 } else { 2 }) {
+            0 => Param::Immediate(self.value()),
+            1 => Param::Data(self.rng.range(0, DATA_SIZE - 1)),
+            _ => Param::Relative(self.rng.range(-self.base, DATA_SIZE - 1 - self.base)),
+        }
+    }
+
+    fn write(&mut self) -> Param {
+        if self.rng.below(500) == 0 {
+            return Param::Address(-self.rng.range(1, 5));
+        }
+        match self.rng.below(if self.dialect.relative_mode { 2 } else { 1 }) {
+            0 => Param::Data(self.rng.range(0, DATA_SIZE - 1)),
+            _ => Param::Relative(self.rng.range(-self.base, DATA_SIZE - 1 - self.base)),
+        }
+    }
+
+    fn push_with_reads(&mut self, opcode: i64, reads: usize, write: bool) {
+        let mut params = Vec::new();
+        for _ in 0..reads {
+            params.push(self.read());
+        }
+        if write {
+            params.push(self.write());
+        }
+        let mut op = Op::new(opcode, params);
+        for (patchable, param) in op.patchable.iter_mut().zip(&op.params) {
+            *patchable = matches!(param, Param::Immediate(_));
+        }
+        self.ops.push(op);
+    }
+
+    fn block(&mut self, length: usize) {
+        for _ in 0..length {
+            self.statement();
+        }
+    }
+
+    fn statement(&mut self) {
+        match self.rng.below(100) {
+            0..=19 => self.push_with_reads(1, 2, true),
+            20..=29 => self.push_with_reads(2, 2, true),
+            30..=39 => self.push_with_reads(7, 2, true),
+            40..=49 => self.push_with_reads(8, 2, true),
+            50..=54 => self.push_with_reads(3, 0, true),
+            55..=64 => self.push_with_reads(4, 1, false),
+            65..=71 if self.depth == 0 && self.fixed_base == 0 && self.dialect.relative_mode => {
+                // Move the relative base, keeping it within the data area.
+                let base = self.rng.range(0, BASE_RANGE);
+                self.ops.push(Op::new(9, vec![Param::Immediate(base - self.base)]));
+                self.base = base;
+            }
+            72..=79 => {
+                // Skip forward over the next statement, or not. The relative
+                // base can't move in there, since it might not happen.
+                let opcode = self.rng.range(5, 6);
+                let condition = self.read();
+                let jump = self.ops.len();
+                self.ops.push(Op::new(opcode, vec![condition, Param::Code(0)]));
+                self.fixed_base += 1;
+                self.statement();
+                self.fixed_base -= 1;
+                let target = self.ops.len();
+                self.ops[jump].params[1] = Param::Code(target);
+            }
+            80..=87 => {
+                // Overwrite an immediate somewhere in the program.
+                let value = self.read();
+                let slot = self.patches;
+                self.patches += 1;
+                self.ops.push(Op::new(1, vec![value, Param::Immediate(0), Param::Patch(slot)]));
+            }
+            88..=95 if self.depth < MAX_DEPTH => {
+                let counter = self.depth;
+                let iterations = self.rng.range(1, 5);
+                self.ops.push(Op::new(1, vec![Param::Immediate(iterations), Param::Immediate(0), Param::Counter(counter)]));
+                let start = self.ops.len();
+                self.depth += 1;
+                let length = self.rng.range(1, 6) as usize;
+                self.block(length);
+                self.depth -= 1;
+                self.ops.push(Op::new(1, vec![Param::Counter(counter), Param::Immediate(-1), Param::Counter(counter)]));
+                self.ops.push(Op::new(5, vec![Param::Counter(counter), Param::Code(start)]));
+            }
+            _ => self.push_with_reads(1, 2, true),
+        }
+    }
+
+    // Lay the program out: code, then the loop counters, then the data area.
+    fn layout(mut self) -> Vec<i64> {
+        let mut addresses = Vec::new();
+        let mut address = 0;
+        for op in &self.ops {
+            addresses.push(address);
+            address += op.params.len() as i64 + 1;
+        }
+        addresses.push(address);
+        // One past the code is the final HALT.
+        let counters = address + 1;
+        let data = counters + MAX_DEPTH as i64;
+
+        // Where each patchable immediate ends up; patches pick one of them.
+        let slots: Vec<i64> = self
+            .ops
+            .iter()
+            .zip(&addresses)
+            .flat_map(|(op, &at)| {
+                op.patchable.iter().enumerate().filter(|(_, &patchable)| patchable).map(move |(index, _)| at + index as i64 + 1)
+            })
+            .collect();
+
+        let mut program = Vec::new();
+        for op in &self.ops {
+            let mut opcode = op.opcode;
+            let mut place = 100;
+            let mut params = Vec::new();
+            for param in &op.params {
+                let (mode, value) = match *param {
+                    Param::Immediate(value) => (1, value),
+                    Param::Data(offset) => (0, data + offset),
+                    Param::Relative(offset) => (2, offset),
+                    Param::Code(index) => (1, addresses[index]),
+                    Param::Counter(counter) => (0, counters + counter as i64),
+                    Param::Address(address) => (0, address),
+                    Param::Patch(slot) => match slots.len() {
+                        0 => (0, data),
+                        n => (0, slots[slot % n]),
+                    },
+                };
+                opcode += mode * place;
+                place *= 10;
+                params.push(value);
+            }
+            program.push(opcode);
+            program.extend(params);
+        }
+        program.push(if self.rng.chance(3) { self.rng.range(10, 98) } else { 99 });
+        program.extend(vec![0; MAX_DEPTH]);
+        for _ in 0..DATA_SIZE {
+            let value = self.value();
+            program.push(value);
+        }
+        program
+    }
+}
+
+fn generate(seed: u64, dialect: Dialect) -> Vec<i64> {
+    let mut generator = Generator {
+        rng: Rng::new(seed),
+        ops: Vec::new(),
+        base: 0,
+        depth: 0,
+        fixed_base: 0,
+        patches: 0,
+        dialect,
+    };
+    // Start with the relative base at the data area.
+    if dialect.relative_mode {
+        generator.ops.push(Op::new(9, vec![Param::Immediate(0)]));
+    }
+    let length = generator.rng.range(5, 40) as usize;
+    generator.block(length);
+    // The first instruction's operand is filled in once the data area's address is known.
+    let mut program = generator.layout();
+    if dialect.relative_mode {
+        program[1] = program.len() as i64 - DATA_SIZE;
+    }
+    program
+}
+
+// Everything about a finished run that the implementations have to agree on.
+#[derive(Debug, Clone, PartialEq)]
+struct Outcome {
+    outputs: Vec<i64>,
+    result: Result<(), IntcodeError>,
+    halted: bool,
+    cursor: usize,
+    relative_base: i64,
+    steps: u64,
+    memory: Vec<i64>,
+}
+
+fn machine(program: &[i64], backend: Backend, inputs: &[i64]) -> Machine {
+    let mut machine = Machine::with_backend(program.to_vec(), backend);
+    machine.set_limits(Limits {
+        max_steps: Some(MAX_STEPS),
+        ..Limits::none()
+    });
+    for &input in inputs {
+        machine.push_input(input);
+    }
+    machine
+}
+
+fn outcome(machine: &Machine, outputs: Vec<i64>, result: Result<(), IntcodeError>) -> Outcome {
+    let memory = machine.memory();
+    Outcome {
+        outputs,
+        result,
+        halted: machine.is_halted(),
+        cursor: machine.cursor(),
+        relative_base: machine.relative_base(),
+        steps: machine.steps(),
+        memory: (0..memory.len()).map(|address| memory.get(address)).collect(),
+    }
+}
+
+// Run with `resume` until the machine halts or fails.
+fn resume_all(machine: &mut Machine, outputs: &mut Vec<i64>) -> Result<(), IntcodeError> {
+    loop {
+        match machine.resume()? {
+            RunState::Output(value) => outputs.push(value),
+            RunState::Halted => return Ok(()),
+            RunState::NeedsInput => return Err(out_of_input(machine)),
+        }
+    }
+}
+
+// What `Machine::run` reports when the program wants more input than it was given.
+fn out_of_input(machine: &Machine) -> IntcodeError {
+    IntcodeError::InputExhausted {
+        cursor: machine.cursor(),
+        opcode_value: machine.read(machine.cursor()),
+    }
+}
+
+fn run_with_backend(program: &[i64], inputs: &[i64], backend: Backend) -> Outcome {
+    let mut machine = machine(program, backend, inputs);
+    let mut outputs = Vec::new();
+    let result = resume_all(&mut machine, &mut outputs);
+    outcome(&machine, outputs, result)
+}
+
+fn run_by_steps(program: &[i64], inputs: &[i64]) -> Outcome {
+    let mut machine = machine(program, Backend::Interpreter, inputs);
+    let mut outputs = Vec::new();
+    let result = loop {
+        match machine.step() {
+            Ok(Some(RunState::Output(value))) => outputs.push(value),
+            Ok(Some(RunState::Halted)) => break Ok(()),
+            Ok(Some(RunState::NeedsInput)) => break Err(out_of_input(&machine)),
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    outcome(&machine, outputs, result)
+}
+
+// Run part of the way on the threaded backend, then finish on a copy: a
+// clone, or a machine saved to a snapshot and loaded again.
+fn run_split(program: &[i64], inputs: &[i64], split: u64, through_snapshot: bool) -> Outcome {
+    let mut machine = machine(program, Backend::Threaded, inputs);
+    let mut outputs = Vec::new();
+    while machine.steps() < split && !machine.is_halted() {
+        match machine.step() {
+            Ok(Some(RunState::Output(value))) => outputs.push(value),
+            Ok(_) => {}
+            Err(e) => return outcome(&machine, outputs, Err(e)),
+        }
+    }
+    let mut rest = if through_snapshot {
+        let mut snapshot = Vec::new();
+        machine.save(&mut snapshot).expect("snapshot to memory failed");
+        Machine::load(&snapshot[..]).expect("snapshot didn't load")
+    } else {
+        machine.clone()
+    };
+    let result = resume_all(&mut rest, &mut outputs);
+    outcome(&rest, outputs, result)
+}
+
+fn check(seed: u64) {
+    let program = generate(seed, FULL);
+    let mut rng = Rng::new(seed ^ 0x5eed);
+    let inputs: Vec<i64> = (0..INPUTS).map(|_| rng.range(-1000, 1000)).collect();
+    let fail = |what: &str, expected: &Outcome, actual: &Outcome| {
+        panic!(
+            "seed {}: {} disagrees with the interpreter\nprogram: {:?}\nexpected: {:?}\nactual: {:?}",
+            seed, what, program, expected, actual
+        )
+    };
+
+    let expected = run_with_backend(&program, &inputs, Backend::Interpreter);
+    if let Err(IntcodeError::InputExhausted { .. } | IntcodeError::StepLimitExceeded { .. }) = expected.result {
+        panic!("seed {}: generated program didn't terminate\nprogram: {:?}", seed, program);
+    }
+    let split = rng.below(expected.steps + 1);
+    let runs = [
+        ("threaded backend", run_with_backend(&program, &inputs, Backend::Threaded)),
+        ("single stepping", run_by_steps(&program, &inputs)),
+        ("clone after some steps", run_split(&program, &inputs, split, false)),
+        ("snapshot after some steps", run_split(&program, &inputs, split, true)),
+    ];
+    for (what, actual) in &runs {
+        if *actual != expected {
+            fail(what, &expected, actual);
+        }
+    }
+
+    // A trace of the run has to replay against the same program.
+    let mut traced = machine(&program, Backend::Threaded, &inputs);
+    traced.start_trace();
+    let mut outputs = Vec::new();
+    let result = resume_all(&mut traced, &mut outputs);
+    let trace = traced.stop_trace();
+    let actual = outcome(&traced, outputs, result);
+    if actual != expected {
+        fail("traced run", &expected, &actual);
+    }
+    if let Some(divergence) = replay(program.clone(), &trace) {
+        panic!("seed {}: trace doesn't replay: {}\nprogram: {:?}", seed, divergence, program);
+    }
+}
+
+// How the crate's machine finished a program, as a legacy machine would see it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum End {
+    Halted,
+    Failed,
+    // The legacy machines panic here in debug builds and wrap in release builds.
+    Overflowed,
+}
+
+#[derive(Debug)]
+struct Reference {
+    end: End,
+    outputs: Vec<i64>,
+    // The program's own memory, before any failing instruction.
+    memory: Vec<i64>,
+    relative_base: i64,
+}
+
+// Run on the crate's machine one step at a time. In the i32 dialect a value
+// that doesn't fit in an i32 counts as an overflow, and the instruction that
+// wrote it is undone.
+fn reference(program: &[i64], inputs: &[i64], dialect: Dialect) -> Reference {
+    let mut machine = machine(program, Backend::Interpreter, inputs);
+    let mut history = History::with_limit(1);
+    let mut outputs = Vec::new();
+    let end = loop {
+        match history.step(&mut machine) {
+            Ok(Some(RunState::Halted)) => break End::Halted,
+            Ok(Some(RunState::Output(value))) => outputs.push(value),
+            Ok(Some(RunState::NeedsInput)) => panic!("program read more than {} inputs", inputs.len()),
+            Ok(None) => {}
+            Err(IntcodeError::Overflow { .. }) => break End::Overflowed,
+            Err(_) => break End::Failed,
+        }
+        let written = history.changes().last().and_then(|change| change.write);
+        if let Some((address, _)) = written {
+            let value = machine.read(address);
+            if value < dialect.smallest || value > dialect.largest {
+                history.step_back(&mut machine);
+                break End::Overflowed;
+            }
+        }
+    };
+    Reference {
+        end,
+        outputs,
+        memory: (0..program.len()).map(|address| machine.read(address)).collect(),
+        relative_base: machine.relative_base(),
+    }
+}
+
+// What a legacy machine left behind. It may only show some of its outputs,
+// and those it kept are `None` if it lost them by panicking.
+#[derive(Debug, PartialEq)]
+struct LegacyRun {
+    finished: bool,
+    outputs: Option<Vec<i64>>,
+    memory: Vec<i64>,
+    relative_base: Option<i64>,
+}
+
+thread_local! {
+    static EXPECTING_PANIC: Cell<bool> = const { Cell::new(false) };
+}
+
+// Run a legacy machine, catching the panics it reports errors with and
+// keeping them out of the test output.
+fn finishes(run: impl FnOnce()) -> bool {
+    static QUIET: Once = Once::new();
+    QUIET.call_once(|| {
+        let report = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !EXPECTING_PANIC.with(Cell::get) {
+                report(info);
+            }
+        }));
+    });
+    EXPECTING_PANIC.with(|expecting| expecting.set(true));
+    let finished = panic::catch_unwind(AssertUnwindSafe(run)).is_ok();
+    EXPECTING_PANIC.with(|expecting| expecting.set(false));
+    finished
+}
+
+fn narrow(values: &[i64]) -> Vec<i32> {
+    values.iter().map(|&value| value as i32).collect()
+}
+
+fn widen(values: &[i32]) -> Vec<i64> {
+    values.iter().map(|&value| value as i64).collect()
+}
+
+// The last output, for the machines that only kept one signal.
+fn last(outputs: &[i64]) -> Vec<i64> {
+    outputs.last().copied().into_iter().collect()
+}
+
+// Compare a legacy run with the crate's machine on the same program and inputs.
+fn compare(seed: u64, what: &str, program: &[i64], reference: &Reference, shown: &[i64], legacy: &LegacyRun) {
+    if reference.end == End::Overflowed && !cfg!(debug_assertions) {
+        return;
+    }
+    let expected = LegacyRun {
+        finished: reference.end == End::Halted,
+        outputs: legacy.outputs.as_ref().map(|_| shown.to_vec()),
+        memory: reference.memory.clone(),
+        relative_base: legacy.relative_base.map(|_| reference.relative_base),
+    };
+    let actual = LegacyRun {
+        memory: legacy.memory[..program.len()].to_vec(),
+        outputs: legacy.outputs.clone(),
+        ..*legacy
+    };
+    if actual != expected {
+        panic!(
+            "seed {}: {} disagrees with the machine, which {:?}\nprogram: {:?}\nexpected: {:?}\nactual: {:?}",
+            seed, what, reference.end, program, expected, actual
+        );
+    }
+}
+
+fn check_legacy(seed: u64) {
+    let mut rng = Rng::new(seed ^ 0x1e9a);
+    let input = rng.range(-1000, 1000);
+    let constant = vec![input; INPUTS];
+
+    // Day 5: free functions over i32 memory, reading any number of inputs.
+    let program = generate(seed, I32);
+    let inputs: Vec<i64> = (0..INPUTS).map(|_| rng.range(-1000, 1000)).collect();
+    let reference = reference(&program, &inputs, I32);
+    let mut memory = narrow(&program);
+    let mut queue: VecDeque<i32> = narrow(&inputs).into();
+    let mut outputs = Vec::new();
+    let finished = finishes(|| legacy::day_5::run_tape(&mut memory, 0, &mut queue, &mut outputs));
+    let run = LegacyRun {
+        finished,
+        outputs: Some(widen(&outputs)),
+        memory: widen(&memory),
+        relative_base: None,
+    };
+    compare(seed, "day 5", &program, &reference, &reference.outputs, &run);
+
+    // Day 7: an amplifier reads its phase, then the same signal every time.
+    let phase = rng.range(0, 9);
+    let inputs: Vec<i64> = Some(phase).into_iter().chain(constant.iter().copied()).collect();
+    let reference = self::reference(&program, &inputs, I32);
+    let mut amplifier = legacy::day_7::Amplifier::new(narrow(&program));
+    let finished = finishes(|| amplifier.run_tape(input as i32, phase as i32));
+    let run = LegacyRun {
+        finished,
+        outputs: Some(amplifier.output_signal.map(i64::from).into_iter().collect()),
+        memory: widen(&amplifier.memory),
+        relative_base: None,
+    };
+    compare(seed, "day 7 amplifier", &program, &reference, &last(&reference.outputs), &run);
+
+    // Day 9: relative mode, and the same input every time.
+    let program = generate(seed, FULL);
+    let reference = self::reference(&program, &constant, FULL);
+    let mut compiler = legacy::day_9::Compiler::new(program.clone());
+    let finished = finishes(|| compiler.run_tape(input));
+    let run = LegacyRun {
+        finished,
+        outputs: Some(compiler.output_signal.into_iter().collect()),
+        memory: compiler.memory.clone(),
+        relative_base: Some(compiler.relative_base),
+    };
+    compare(seed, "day 9 compiler", &program, &reference, &last(&reference.outputs), &run);
+
+    // Day 11: outputs come back in pairs. It returns a pair before moving
+    // past the second output, so the next call outputs that again and the
+    // pairs overlap.
+    let mut compiler = legacy::day_11::Compiler::new(program.clone());
+    let mut outputs = Vec::new();
+    let finished = finishes(|| {
+        while let Some(pair) = compiler.run_tape(input) {
+            outputs.extend_from_slice(&pair);
+        }
+    });
+    let run = LegacyRun {
+        finished,
+        outputs: Some(outputs),
+        memory: compiler.memory.clone(),
+        relative_base: Some(compiler.relative_base),
+    };
+    let pairs: Vec<i64> = reference.outputs.windows(2).flatten().copied().collect();
+    compare(seed, "day 11 compiler", &program, &reference, &pairs, &run);
+
+    // Day 13: the joystick is steered with the keys 4, 5 and 6.
+    let tilts: Vec<i64> = (0..INPUTS).map(|_| rng.range(-1, 1)).collect();
+    let reference = self::reference(&program, &tilts, FULL);
+    let mut game = legacy::day_13::ArcadeGame::new(program.clone());
+    let mut keys: VecDeque<i64> = tilts.iter().map(|tilt| tilt + 5).collect();
+    let mut outputs = None;
+    let finished = finishes(|| outputs = Some(game.run_tape(&mut keys)));
+    let run = LegacyRun {
+        finished,
+        outputs,
+        memory: game.memory.clone(),
+        relative_base: Some(game.relative_base),
+    };
+    compare(seed, "day 13 arcade game", &program, &reference, &reference.outputs, &run);
+}
+
+fn setting(name: &str, default: u64) -> u64 {
+    env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+#[test]
+fn implementations_agree_on_random_programs() {
+    let cases = setting("INTCODE_FUZZ_CASES", DEFAULT_CASES);
+    let first = setting("INTCODE_FUZZ_SEED", 1);
+    for seed in first..first + cases {
+        check(seed);
+    }
+}
+
+#[test]
+fn legacy_machines_agree_on_random_programs() {
+    let cases = setting("INTCODE_FUZZ_CASES", DEFAULT_CASES);
+    let first = setting("INTCODE_FUZZ_SEED", 1);
+    for seed in first..first + cases {
+        check_legacy(seed);
+    }
+}
+
+#[test]
+fn generated_programs_mostly_halt() {
+    // Make sure the generator isn't just producing programs that fail straight away.
+    let halted = (1..=200)
+        .filter(|&seed| {
+            let inputs = vec![0; INPUTS];
+            run_with_backend(&generate(seed, FULL), &inputs, Backend::Interpreter).halted
+        })
+        .count();
+    assert!(halted > 100, "only {} of 200 generated programs halted", halted);
+}
@@ -0,0 +1,518 @@
+// Regression tests: the worked examples from the puzzle statements for
+// days 2, 5, 7 and 9, and the answers for each day's input.txt, most of
+// them on both backends. The puzzles also exercise the symbolic solver, the
+// device bus, coverage and the assembler.
+
+use std::fs;
+use std::path::Path;
+
+use intcode::{
+    assemble, disassemble, AmplifierChain, AmplifierTopology, Backend, Bus, Color, Coverage, Device, DeviceError, Goal, Joystick, Machine, PaintRobot,
+    RunState, Symbol, SymbolicMachine, Tile, TileScreen, Tilt,
+};
+
+const BACKENDS: [Backend; 2] = [Backend::Interpreter, Backend::Threaded];
+
+// A program, its inputs, and the outputs it has to produce.
+struct OutputCase {
+    name: &'static str,
+    program: &'static str,
+    inputs: &'static [i64],
+    outputs: &'static [i64],
+}
+
+// A program and what its memory has to look like when it halts.
+struct MemoryCase {
+    name: &'static str,
+    program: &'static str,
+    memory: &'static [i64],
+}
+
+// An amplifier program, its best phase settings and the signal they give.
+struct AmplifierCase {
+    name: &'static str,
+    program: &'static str,
+    topology: AmplifierTopology,
+    phases: [i64; 5],
+    signal: i64,
+}
+
+// Day 5's larger example: 999 below 8, 1000 for 8, 1001 above 8.
+const COMPARE_TO_8: &str = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+const MEMORY_CASES: &[MemoryCase] = &[
+    MemoryCase {
+        name: "day 2: worked example",
+        program: "1,9,10,3,2,3,11,0,99,30,40,50",
+        memory: &[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50],
+    },
+    MemoryCase {
+        name: "day 2: 1 + 1",
+        program: "1,0,0,0,99",
+        memory: &[2, 0, 0, 0, 99],
+    },
+    MemoryCase {
+        name: "day 2: 3 * 2",
+        program: "2,3,0,3,99",
+        memory: &[2, 3, 0, 6, 99],
+    },
+    MemoryCase {
+        name: "day 2: 99 * 99",
+        program: "2,4,4,5,99,0",
+        memory: &[2, 4, 4, 5, 99, 9801],
+    },
+    MemoryCase {
+        name: "day 2: overwrites its own halt",
+        program: "1,1,1,4,99,5,6,0,99",
+        memory: &[30, 1, 1, 4, 2, 5, 6, 0, 99],
+    },
+    MemoryCase {
+        name: "day 5: parameter modes",
+        program: "1002,4,3,4,33",
+        memory: &[1002, 4, 3, 4, 99],
+    },
+    MemoryCase {
+        name: "day 5: negative immediates",
+        program: "1101,100,-1,4,0",
+        memory: &[1101, 100, -1, 4, 99],
+    },
+];
+
+const OUTPUT_CASES: &[OutputCase] = &[
+    OutputCase {
+        name: "day 5: echo",
+        program: "3,0,4,0,99",
+        inputs: &[42],
+        outputs: &[42],
+    },
+    OutputCase {
+        name: "day 5: equal to 8, position mode, 8",
+        program: "3,9,8,9,10,9,4,9,99,-1,8",
+        inputs: &[8],
+        outputs: &[1],
+    },
+    OutputCase {
+        name: "day 5: equal to 8, position mode, 7",
+        program: "3,9,8,9,10,9,4,9,99,-1,8",
+        inputs: &[7],
+        outputs: &[0],
+    },
+    OutputCase {
+        name: "day 5: less than 8, position mode, 7",
+        program: "3,9,7,9,10,9,4,9,99,-1,8",
+        inputs: &[7],
+        outputs: &[1],
+    },
+    OutputCase {
+        name: "day 5: less than 8, position mode, 8",
+        program: "3,9,7,9,10,9,4,9,99,-1,8",
+        inputs: &[8],
+        outputs: &[0],
+    },
+    OutputCase {
+        name: "day 5: equal to 8, immediate mode, 8",
+        program: "3,3,1108,-1,8,3,4,3,99",
+        inputs: &[8],
+        outputs: &[1],
+    },
+    OutputCase {
+        name: "day 5: equal to 8, immediate mode, 9",
+        program: "3,3,1108,-1,8,3,4,3,99",
+        inputs: &[9],
+        outputs: &[0],
+    },
+    OutputCase {
+        name: "day 5: less than 8, immediate mode, -3",
+        program: "3,3,1107,-1,8,3,4,3,99",
+        inputs: &[-3],
+        outputs: &[1],
+    },
+    OutputCase {
+        name: "day 5: less than 8, immediate mode, 8",
+        program: "3,3,1107,-1,8,3,4,3,99",
+        inputs: &[8],
+        outputs: &[0],
+    },
+    OutputCase {
+        name: "day 5: jump, position mode, 0",
+        program: "3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9",
+        inputs: &[0],
+        outputs: &[0],
+    },
+    OutputCase {
+        name: "day 5: jump, position mode, 5",
+        program: "3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9",
+        inputs: &[5],
+        outputs: &[1],
+    },
+    OutputCase {
+        name: "day 5: jump, immediate mode, 0",
+        program: "3,3,1105,-1,9,1101,0,0,12,4,12,99,1",
+        inputs: &[0],
+        outputs: &[0],
+    },
+    OutputCase {
+        name: "day 5: jump, immediate mode, -1",
+        program: "3,3,1105,-1,9,1101,0,0,12,4,12,99,1",
+        inputs: &[-1],
+        outputs: &[1],
+    },
+    OutputCase {
+        name: "day 5: compare to 8, below",
+        program: COMPARE_TO_8,
+        inputs: &[7],
+        outputs: &[999],
+    },
+    OutputCase {
+        name: "day 5: compare to 8, equal",
+        program: COMPARE_TO_8,
+        inputs: &[8],
+        outputs: &[1000],
+    },
+    OutputCase {
+        name: "day 5: compare to 8, above",
+        program: COMPARE_TO_8,
+        inputs: &[9],
+        outputs: &[1001],
+    },
+    OutputCase {
+        name: "day 9: quine",
+        program: QUINE,
+        inputs: &[],
+        outputs: &[109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99],
+    },
+    OutputCase {
+        name: "day 9: 16-digit number",
+        program: "1102,34915192,34915192,7,4,7,99,0",
+        inputs: &[],
+        outputs: &[1219070632396864],
+    },
+    OutputCase {
+        name: "day 9: large number",
+        program: "104,1125899906842624,99",
+        inputs: &[],
+        outputs: &[1125899906842624],
+    },
+];
+
+const AMPLIFIER_CASES: &[AmplifierCase] = &[
+    AmplifierCase {
+        name: "day 7: first example",
+        program: "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0",
+        topology: AmplifierTopology::Linear,
+        phases: [4, 3, 2, 1, 0],
+        signal: 43210,
+    },
+    AmplifierCase {
+        name: "day 7: second example",
+        program: "3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0",
+        topology: AmplifierTopology::Linear,
+        phases: [0, 1, 2, 3, 4],
+        signal: 54321,
+    },
+    AmplifierCase {
+        name: "day 7: third example",
+        program: "3,31,3,32,1002,32,10,32,1001,31,-2,31,1007,31,0,33,1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0",
+        topology: AmplifierTopology::Linear,
+        phases: [1, 0, 4, 3, 2],
+        signal: 65210,
+    },
+    AmplifierCase {
+        name: "day 7: first feedback example",
+        program: "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        topology: AmplifierTopology::Feedback,
+        phases: [9, 8, 7, 6, 5],
+        signal: 139629729,
+    },
+    AmplifierCase {
+        name: "day 7: second feedback example",
+        program: "3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,-5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10",
+        topology: AmplifierTopology::Feedback,
+        phases: [9, 7, 8, 5, 6],
+        signal: 18216,
+    },
+];
+
+fn parse(program: &str) -> Vec<i64> {
+    intcode::parse_program(program).expect("example program doesn't parse")
+}
+
+// The puzzle input for a day, from its crate next to this one.
+fn puzzle_input(day: &str) -> Vec<i64> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(day).join("input.txt");
+    let contents = fs::read_to_string(&path).unwrap_or_else(|e| panic!("can't read {}: {}", path.display(), e));
+    parse(&contents)
+}
+
+fn run(program: Vec<i64>, backend: Backend, inputs: &[i64]) -> (Machine, Vec<i64>) {
+    let mut machine = Machine::with_backend(program, backend);
+    for &input in inputs {
+        machine.push_input(input);
+    }
+    let outputs = machine.run().expect("program failed");
+    (machine, outputs)
+}
+
+#[test]
+fn examples_leave_the_expected_memory() {
+    for case in MEMORY_CASES {
+        for backend in BACKENDS {
+            let (machine, _) = run(parse(case.program), backend, &[]);
+            let memory: Vec<i64> = (0..case.memory.len()).map(|address| machine.read(address)).collect();
+            assert_eq!(memory, case.memory, "{} ({:?})", case.name, backend);
+        }
+    }
+}
+
+#[test]
+fn examples_produce_the_expected_outputs() {
+    for case in OUTPUT_CASES {
+        for backend in BACKENDS {
+            let (_, outputs) = run(parse(case.program), backend, case.inputs);
+            assert_eq!(outputs, case.outputs, "{} ({:?})", case.name, backend);
+        }
+    }
+}
+
+#[test]
+fn amplifier_examples_find_the_best_phases() {
+    for case in AMPLIFIER_CASES {
+        let chain = AmplifierChain::new(parse(case.program), case.topology);
+        assert_eq!(chain.signal(&case.phases), Ok(case.signal), "{}", case.name);
+
+        let mut phases = case.phases;
+        phases.sort_unstable();
+        let best = chain.best(&phases).expect(case.name).expect(case.name);
+        assert_eq!(best.signal, case.signal, "{}", case.name);
+        assert_eq!(best.phases, case.phases, "{}", case.name);
+    }
+}
+
+#[test]
+fn day_2_answers() {
+    let program = puzzle_input("day_2");
+    let run_with = |noun, verb| {
+        let mut machine = Machine::new(program.clone());
+        machine.write(1, noun);
+        machine.write(2, verb);
+        machine.run().expect("day 2 program failed");
+        machine.read(0)
+    };
+    assert_eq!(run_with(12, 2), 3516593);
+
+    let (noun, verb) = (0..100)
+        .flat_map(|noun| (0..100).map(move |verb| (noun, verb)))
+        .find(|&(noun, verb)| run_with(noun, verb) == 19690720)
+        .expect("no noun and verb give 19690720");
+    assert_eq!(100 * noun + verb, 7749);
+}
+
+#[test]
+fn day_2_solved_symbolically() {
+    let mut solver = SymbolicMachine::new(puzzle_input("day_2"));
+    solver.symbolic_memory(1, 0..=99);
+    solver.symbolic_memory(2, 0..=99);
+    let solution = solver
+        .solve(Goal::MemoryAtHalt { address: 0, value: 19690720 })
+        .expect("day 2 can't be solved symbolically")
+        .expect("no noun and verb give 19690720");
+    assert_eq!(solution.value(Symbol::Memory(1)), Some(77));
+    assert_eq!(solution.value(Symbol::Memory(2)), Some(49));
+}
+
+#[test]
+fn compare_to_8_solved_symbolically() {
+    let mut solver = SymbolicMachine::new(parse(COMPARE_TO_8));
+    let input = solver.push_symbolic_input(-1000..=1000);
+    let solve = |goal| solver.solve(goal).expect("example can't be solved symbolically").map(|s| s.value(input));
+    assert_eq!(solve(Goal::Output(1000)), Some(Some(8)));
+    assert_eq!(solve(Goal::Output(1001)), Some(Some(9)));
+    assert_eq!(solve(Goal::Output(999)), Some(Some(-1000)));
+    assert_eq!(solve(Goal::Output(5)), None);
+    assert_eq!(solver.paths().expect("example can't be explored").len(), 3);
+}
+
+#[test]
+fn day_5_answers() {
+    for backend in BACKENDS {
+        let (_, outputs) = run(puzzle_input("day_5"), backend, &[1]);
+        let (diagnostic, checks) = outputs.split_last().expect("no output");
+        assert!(checks.iter().all(|&check| check == 0), "failed checks: {:?}", checks);
+        assert_eq!(*diagnostic, 12896948);
+
+        let (_, outputs) = run(puzzle_input("day_5"), backend, &[5]);
+        assert_eq!(outputs, [7704130]);
+    }
+}
+
+#[test]
+fn day_7_answers() {
+    let program = puzzle_input("day_7");
+    let linear = AmplifierChain::new(program.clone(), AmplifierTopology::Linear);
+    let best = linear.best(&[0, 1, 2, 3, 4]).unwrap().unwrap();
+    assert_eq!((best.signal, best.phases), (255840, vec![0, 2, 1, 4, 3]));
+
+    let feedback = AmplifierChain::new(program, AmplifierTopology::Feedback);
+    let best = feedback.best(&[5, 6, 7, 8, 9]).unwrap().unwrap();
+    assert_eq!((best.signal, best.phases), (84088865, vec![7, 5, 9, 8, 6]));
+}
+
+#[test]
+fn day_9_answers() {
+    for backend in BACKENDS {
+        assert_eq!(run(puzzle_input("day_9"), backend, &[1]).1, [3765554916]);
+        assert_eq!(run(puzzle_input("day_9"), backend, &[2]).1, [76642]);
+    }
+}
+
+#[test]
+fn day_13_answer() {
+    // The tiles are drawn as (x, y, tile id) triples; 2 is a block.
+    for backend in BACKENDS {
+        let (_, outputs) = run(puzzle_input("day_13"), backend, &[]);
+        let blocks = outputs.chunks(3).filter(|tile| tile[2] == 2).count();
+        assert_eq!(blocks, 193);
+    }
+}
+
+#[test]
+fn day_11_and_13_on_the_device_bus() {
+    for backend in BACKENDS {
+        let mut machine = Machine::with_backend(puzzle_input("day_11"), backend);
+        let mut robot = PaintRobot::new();
+        assert_eq!(Bus::new().attach(&mut robot).run(&mut machine), Ok(RunState::Halted));
+        assert_eq!(robot.count(Color::White), 1489);
+
+        let mut machine = Machine::with_backend(puzzle_input("day_13"), backend);
+        let mut screen = TileScreen::new();
+        assert_eq!(Bus::new().attach(&mut screen).run(&mut machine), Ok(RunState::Halted));
+        assert_eq!(screen.count(Tile::Block), 193);
+    }
+}
+
+// Free play: the game asks for a joystick move every frame, and stops
+// waiting once the script runs out.
+#[test]
+fn day_13_free_play_with_a_scripted_joystick() {
+    let mut program = puzzle_input("day_13");
+    program[0] = 2;
+    let mut machine = Machine::new(program);
+    let mut screen = TileScreen::new();
+    let mut joystick = Joystick::scripted(vec![Tilt::Left, Tilt::Neutral, Tilt::Right]);
+    let state = Bus::new().attach(&mut screen).attach(&mut joystick).run(&mut machine);
+    assert_eq!(state, Ok(RunState::NeedsInput));
+    assert_eq!(joystick.moves(), 3);
+    assert_eq!(screen.score(), Some(0));
+    assert_eq!(screen.count(Tile::Ball), 1);
+}
+
+// A command split by a request for input is only completed if the same bus
+// is run again, and can be put back when the machine is rewound.
+#[test]
+fn commands_outlive_requests_for_input_on_the_same_bus() {
+    // Draws a block at (1, 2) with the tile id read part way through.
+    let mut machine = Machine::new(vec![104, 1, 104, 2, 3, 9, 4, 9, 99, 0]);
+    let mut screen = TileScreen::new();
+    let mut bus = Bus::new().attach(&mut screen);
+    assert_eq!(bus.run(&mut machine), Ok(RunState::NeedsInput));
+    assert_eq!(bus.pending(), &[1, 2]);
+    let rewind = (machine.clone(), bus.pending().to_vec());
+
+    bus.set_pending(Vec::new());
+    machine.push_input(2);
+    assert!(bus.run(&mut machine).is_err());
+
+    machine = rewind.0;
+    bus.set_pending(rewind.1);
+    machine.push_input(2);
+    assert_eq!(bus.run(&mut machine), Ok(RunState::Halted));
+    drop(bus);
+    assert_eq!(screen.count(Tile::Block), 1);
+}
+
+// A new peripheral only needs a Device impl: this one watches the screen and
+// keeps the paddle under the ball until every block is gone.
+struct Player {
+    screen: TileScreen,
+}
+
+impl Device for Player {
+    fn command_len(&self) -> usize {
+        self.screen.command_len()
+    }
+
+    fn command(&mut self, values: &[i64]) -> Result<(), DeviceError> {
+        self.screen.command(values)
+    }
+
+    fn input(&mut self) -> Option<i64> {
+        let (ball, _) = self.screen.find(Tile::Ball)?;
+        let (paddle, _) = self.screen.find(Tile::Paddle)?;
+        Some((ball - paddle).signum())
+    }
+}
+
+#[test]
+fn day_13_played_to_the_end() {
+    let mut program = puzzle_input("day_13");
+    program[0] = 2;
+    let mut machine = Machine::new(program);
+    let mut player = Player { screen: TileScreen::new() };
+    assert_eq!(Bus::new().attach(&mut player).run(&mut machine), Ok(RunState::Halted));
+    assert_eq!(player.screen.count(Tile::Block), 0);
+    assert!(player.screen.score().unwrap_or(0) > 0);
+}
+
+#[test]
+fn disassembly_assembles_back_to_the_program() {
+    // The extremes of an i64, as data and as immediates.
+    let extremes = vec![
+        1101,
+        i64::MAX,
+        i64::MIN,
+        9,
+        4,
+        9,
+        99,
+        i64::MIN,
+        i64::MAX,
+        0,
+        -1,
+    ];
+    let programs = ["day_2", "day_5", "day_7", "day_9", "day_11", "day_13"]
+        .iter()
+        .map(|day| (day.to_string(), puzzle_input(day)))
+        .chain(Some(("extremes".to_string(), extremes)));
+    for (name, program) in programs {
+        let listing = disassemble(&program);
+        let assembled = assemble(&listing).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert_eq!(assembled, program, "{}", name);
+    }
+    assert!(assemble("x: .data 9223372036854775807+1").is_err());
+}
+
+// Day 5's diagnostic patches the instruction at 6 with its input: the two
+// parts run different code there, and together they cover nearly all of it.
+#[test]
+fn day_5_coverage() {
+    let program = puzzle_input("day_5");
+    let mut coverage = Coverage::new();
+    for input in [1, 5] {
+        let mut machine = Machine::new(program.clone());
+        let mut outputs = Vec::new();
+        let state = coverage.run_with(&mut machine, &mut std::collections::VecDeque::from(vec![input]), &mut outputs);
+        assert_eq!(state, Ok(RunState::Halted));
+    }
+    assert_eq!(coverage.executed(6), 2);
+    assert_eq!(coverage.branch(6).map(|branch| (branch.taken, branch.not_taken)), Some((1, 0)));
+    assert_eq!(coverage.writes(6), 2);
+    assert!(coverage.reads(225) > 0);
+
+    let annotated = coverage.to_annotated(&program);
+    assert!(annotated.starts_with("; 163 of 178 instructions executed\n"), "{}", annotated);
+    assert!(annotated.contains("0006: JT #1, #238 ; rewritten at run time, also ran as ADD #1, #238 -> [225]"));
+    let lcov = coverage.to_lcov(&program, "day_5.asm");
+    assert!(lcov.starts_with("TN:\nSF:day_5.asm\n"));
+    assert!(lcov.contains("\nLF:178\nLH:163\nend_of_record\n"), "{}", lcov);
+}
@@ -0,0 +1,141 @@
+// Running a program forwards and then all the way back with a History has to
+// leave the machine as it started, and running it forwards again has to give
+// the same outputs.
+
+use std::fs;
+use std::path::Path;
+
+use intcode::{Debugger, History, Machine, RunState};
+
+fn puzzle_input(day: &str) -> Vec<i64> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(day).join("input.txt");
+    let contents = fs::read_to_string(&path).unwrap_or_else(|e| panic!("can't read {}: {}", path.display(), e));
+    intcode::parse_program(&contents).expect("puzzle input doesn't parse")
+}
+
+// Step until the program halts or wants input that isn't there, returning the outputs.
+fn run(history: &mut History, machine: &mut Machine) -> Vec<i64> {
+    let mut outputs = Vec::new();
+    loop {
+        match history.step(machine).expect("program failed") {
+            Some(RunState::Output(value)) => outputs.push(value),
+            Some(_) => return outputs,
+            None => {}
+        }
+    }
+}
+
+#[test]
+fn rewinding_everything_restores_the_machine() {
+    for (day, inputs) in [("day_5", vec![5]), ("day_9", vec![1]), ("day_11", vec![0; 50])] {
+        let program = puzzle_input(day);
+        let mut machine = Machine::new(program.clone());
+        for &input in &inputs {
+            machine.push_input(input);
+        }
+        let start = machine.clone();
+        let mut history = History::new();
+        let outputs = run(&mut history, &mut machine);
+        assert_eq!(history.len() as u64, machine.steps(), "{}", day);
+        assert_eq!(history.outputs(), outputs.len(), "{}", day);
+
+        while history.step_back(&mut machine).is_some() {}
+        assert_eq!(machine.cursor(), 0, "{}", day);
+        assert_eq!(machine.relative_base(), 0, "{}", day);
+        assert_eq!(machine.steps(), 0, "{}", day);
+        assert_eq!(machine.pending_inputs(), inputs.len(), "{}", day);
+        for address in 0..machine.memory().len() {
+            assert_eq!(machine.read(address), start.read(address), "{}: [{}]", day, address);
+        }
+        assert_eq!(run(&mut history, &mut machine), outputs, "{}", day);
+    }
+}
+
+#[test]
+fn history_finds_writes_and_outputs() {
+    // Counts down from 3, outputting each value: [12] is the counter.
+    let program = vec![4, 12, 1001, 12, -1, 12, 1005, 12, 0, 4, 12, 99, 3];
+    let mut machine = Machine::new(program);
+    let mut history = History::new();
+    assert_eq!(run(&mut history, &mut machine), vec![3, 2, 1, 0]);
+
+    let second = *history.output(2).expect("second output forgotten");
+    assert_eq!(second.output, Some(2));
+    assert_eq!(second.cursor, 0);
+    let last_write = *history.last_write(12).expect("write to the counter forgotten");
+    assert_eq!(last_write.write, Some((12, 1)));
+
+    // A short history forgets the oldest instructions.
+    let mut machine = Machine::new(vec![4, 12, 1001, 12, -1, 12, 1005, 12, 0, 4, 12, 99, 3]);
+    let mut history = History::with_limit(2);
+    run(&mut history, &mut machine);
+    assert_eq!(history.len(), 2);
+    assert!(history.output(1).is_none());
+    assert_eq!(history.output(4).and_then(|change| change.output), Some(0));
+}
+
+#[test]
+fn debugger_runs_backwards() {
+    let mut debugger = Debugger::new(vec![4, 12, 1001, 12, -1, 12, 1005, 12, 0, 4, 12, 99, 3]);
+    debugger.execute("continue");
+    assert!(debugger.machine().is_halted());
+
+    let text = debugger.execute("when-output 2");
+    assert!(text.starts_with("output 2 (2) was produced at step 3"), "{}", text);
+    assert_eq!(debugger.machine().cursor(), 0);
+
+    let text = debugger.execute("reverse-continue 12");
+    assert!(text.starts_with("step 1: [12] 3 -> 2"), "{}", text);
+    assert_eq!(debugger.machine().steps(), 1);
+
+    debugger.execute("reverse-step");
+    assert_eq!(debugger.machine().steps(), 0);
+    debugger.execute("break 2");
+    debugger.execute("continue");
+    assert_eq!(debugger.machine().steps(), 1);
+    let text = debugger.execute("reverse-continue");
+    assert!(text.contains("at the start of the history (step 0)"), "{}", text);
+}
+
+#[test]
+fn pokes_are_undone_like_instructions() {
+    let mut debugger = Debugger::new(vec![4, 12, 1001, 12, -1, 12, 1005, 12, 0, 4, 12, 99, 3]);
+    debugger.execute("continue");
+    debugger.execute("poke 12 7");
+    assert_eq!(debugger.machine().read(12), 7);
+    assert!(debugger.machine().is_halted());
+
+    debugger.execute("reverse-step");
+    assert_eq!(debugger.machine().read(12), 0);
+    assert!(debugger.machine().is_halted());
+
+    let text = debugger.execute("poke 1000000000000 1");
+    assert!(text.contains("beyond memory limit"), "{}", text);
+}
+
+#[test]
+fn debugger_rejects_ranges_past_the_address_space() {
+    let mut debugger = Debugger::new(vec![4, 12, 1001, 12, -1, 12, 1005, 12, 0, 4, 12, 99, 3]);
+    assert_eq!(debugger.execute("print 11 2"), "0011: 99, 3");
+    assert!(debugger.execute("print 18446744073709551615 2").contains("out of range"));
+    assert!(debugger.execute("print 0 1000000").contains("more than"));
+    assert!(debugger.execute("list 0 1000000").contains("more than"));
+    assert_eq!(debugger.execute("list 18446744073709551614 5").lines().count(), 2);
+}
+
+#[test]
+fn reverse_continue_undoes_the_poke_it_reports() {
+    let mut debugger = Debugger::new(vec![4, 12, 1001, 12, -1, 12, 1005, 12, 0, 4, 12, 99, 3]);
+    debugger.execute("step");
+    debugger.execute("poke 12 9");
+    let text = debugger.execute("reverse-continue 12");
+    assert!(text.starts_with("step 1: [12] 3 -> 9\n"), "{}", text);
+    assert_eq!(debugger.machine().read(12), 3);
+    assert_eq!(debugger.machine().steps(), 1);
+
+    // Going back to an output undoes pokes made after it, too.
+    debugger.execute("poke 12 9");
+    debugger.execute("when-output 1");
+    assert_eq!(debugger.machine().read(12), 3);
+    assert_eq!(debugger.machine().steps(), 0);
+}
@@ -0,0 +1,144 @@
+// The compiler's output, built with rustc, has to print the same outputs
+// as a Machine running the same program with the same inputs, and fail in
+// the same way when the Machine fails.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+use intcode::Machine;
+
+// A day's program, with the first few values replaced, and its inputs.
+struct Case {
+    name: &'static str,
+    day: &'static str,
+    patch: &'static [i64],
+    inputs: &'static [i64],
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "day_2",
+        day: "day_2",
+        patch: &[1, 12, 2],
+        inputs: &[],
+    },
+    Case {
+        name: "day_5_part_1",
+        day: "day_5",
+        patch: &[],
+        inputs: &[1],
+    },
+    Case {
+        name: "day_5_part_2",
+        day: "day_5",
+        patch: &[],
+        inputs: &[5],
+    },
+    Case {
+        name: "day_7_amplifier",
+        day: "day_7",
+        patch: &[],
+        inputs: &[3, 17],
+    },
+    Case {
+        name: "day_7_feedback_amplifier",
+        day: "day_7",
+        patch: &[],
+        inputs: &[7, 0, 5, 12, 19, 40],
+    },
+    Case {
+        name: "day_9_part_1",
+        day: "day_9",
+        patch: &[],
+        inputs: &[1],
+    },
+    Case {
+        name: "day_9_part_2",
+        day: "day_9",
+        patch: &[],
+        inputs: &[2],
+    },
+    // The robot's camera always sees black; the program runs until it wants more.
+    Case {
+        name: "day_11",
+        day: "day_11",
+        patch: &[],
+        inputs: &[0; 200],
+    },
+    Case {
+        name: "day_13_screen",
+        day: "day_13",
+        patch: &[],
+        inputs: &[],
+    },
+    // Free play with the joystick held left, then right: the game rewrites
+    // its own code as it goes.
+    Case {
+        name: "day_13_free_play",
+        day: "day_13",
+        patch: &[2],
+        inputs: &[-1, -1, -1, 1, 1, 1, 1, 1, 0, 0, 0, -1, 1, 0, 1, 1, -1, -1, 0, 0],
+    },
+];
+
+fn program(case: &Case) -> Vec<i64> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join(case.day).join("input.txt");
+    let contents = fs::read_to_string(&path).unwrap_or_else(|e| panic!("can't read {}: {}", path.display(), e));
+    let mut program = intcode::parse_program(&contents).expect("puzzle input doesn't parse");
+    program[..case.patch.len()].copy_from_slice(case.patch);
+    program
+}
+
+// What the Machine prints: its outputs, then the error if it failed.
+fn interpret(program: Vec<i64>, inputs: &[i64]) -> String {
+    let mut machine = Machine::new(program);
+    for &input in inputs {
+        machine.push_input(input);
+    }
+    let mut printed = String::new();
+    loop {
+        match machine.run_until_output() {
+            Ok(Some(value)) => printed.push_str(&format!("{}\n", value)),
+            Ok(None) => return printed,
+            Err(e) => {
+                printed.push_str(&format!("Application error: {}\n", e));
+                return printed;
+            }
+        }
+    }
+}
+
+fn compile(directory: &Path, name: &str, program: &[i64]) -> PathBuf {
+    let source = directory.join(format!("{}.rs", name));
+    let binary = directory.join(name);
+    fs::write(&source, intcode::transpile(program)).expect("can't write the compiled program");
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let status = Command::new(rustc)
+        .args(["--edition", "2018", "-o"])
+        .arg(&binary)
+        .arg(&source)
+        .status()
+        .expect("can't run rustc");
+    assert!(status.success(), "{}: rustc failed on {}", name, source.display());
+    binary
+}
+
+#[test]
+fn compiled_programs_match_the_interpreter() {
+    let directory = env::temp_dir().join(format!("intcode-transpile-{}", process::id()));
+    fs::create_dir_all(&directory).expect("can't create a directory for the compiled programs");
+    for case in CASES {
+        let program = program(case);
+        let expected = interpret(program.clone(), case.inputs);
+        let binary = compile(&directory, case.name, &program);
+        let inputs: Vec<String> = case.inputs.iter().map(|input| input.to_string()).collect();
+        // Run with stdin closed, so asking for more input fails just as it does on the Machine.
+        let output = Command::new(&binary).args(&inputs).stdin(Stdio::null()).output().expect("can't run the compiled program");
+        let actual = String::from_utf8(output.stdout).expect("compiled program printed something that isn't UTF-8");
+        assert_eq!(actual, expected, "{}", case.name);
+        assert_eq!(output.status.success(), !expected.contains("Application error"), "{}", case.name);
+    }
+    let _ = fs::remove_dir_all(&directory);
+}